@@ -1,9 +1,20 @@
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
+use std::time::Duration;
 
 use soloud::*;
 
-use crate::sys::resource_manager::{ResourceManager, SoundID};
+use crate::audio::backend::{AudioBackend, AudioClock, create_soloud, mix_frames, write_wav_file};
+use crate::audio::filters::{Filter, FilterParam, FilterSlots, set_filter_param};
+use crate::audio::mixer::{AudioBus, BusId, BusSettings, DEFAULT_BUSES, MixerSettings};
+use crate::audio::music::MusicPlayer;
+use crate::audio::playback::{PlayParams, SoundInstance};
+use crate::audio::spatial::{Emitter, SpatialAudio};
+use crate::audio::voices::{VoiceLimit, VoiceLimiter};
+use crate::math::geo::V2;
+use crate::sys::handle::ResourceId;
+use crate::sys::resource_manager::{ResourceManager, SILENT_SOUND_ID, SoundID};
 
 pub struct Sound {
     pub id: SoundID,
@@ -12,12 +23,27 @@ pub struct Sound {
 pub struct SoundData {
     pub id: SoundID,
     pub wav: Wav,
+    pub(crate) filters: FilterSlots,
+}
+
+// A sound decoded by a loader thread. Until it is played a Wav is only sample data that no soloud
+// instance points at, so it can be handed to the main thread.
+pub struct DecodedSound(Wav);
+
+unsafe impl Send for DecodedSound {}
+
+impl DecodedSound {
+    pub(crate) fn decode (bytes: &[u8]) -> Result<Self, SoloudError> {
+        let mut wav = audio::Wav::default();
+        wav.load_mem(bytes)?;
+        Ok(Self(wav))
+    }
 }
 
 impl Default for Sound {
     fn default() -> Self {
         Self {
-            id: 0,
+            id: SILENT_SOUND_ID,
         }
     }
 }
@@ -33,31 +59,418 @@ impl Sound {
 impl SoundData {
     pub fn from_bytes (bytes: &[u8]) -> Result<Self, SoloudError> {
         let mut wav = audio::Wav::default();
-        wav.load_mem(bytes).unwrap();
+        wav.load_mem(bytes)?;
 
         Ok(Self{
-            id: 0,
+            id: ResourceId::invalid(),
             wav,
+            filters: FilterSlots::default(),
         })
     }
+
+    pub(crate) fn from_decoded (decoded: DecodedSound) -> Self {
+        Self {
+            id: ResourceId::invalid(),
+            wav: decoded.0,
+            filters: FilterSlots::default(),
+        }
+    }
+
+    // A few milliseconds of 16 bit mono silence, used in place of sounds that failed to load
+    pub fn silent () -> Self {
+        const SAMPLE_RATE: u32 = 44100;
+        const SAMPLES: u32 = 64;
+
+        let data_len = SAMPLES * 2;
+        let mut bytes: Vec<u8> = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(44 + data_len as usize, 0);
+
+        Self::from_bytes(&bytes).expect("Could not create silent sound!")
+    }
 }
 
 pub struct AudioSubsystem {
     res: Rc<RefCell<ResourceManager>>,
-    sound: Soloud,
+    sound: Rc<RefCell<Soloud>>,
+    buses: Vec<AudioBus>,
+    paused: bool,
+    spatial: SpatialAudio,
+    backend: AudioBackend,
+    clock: AudioClock,
+    voices: RefCell<VoiceLimiter>,
+
+    pub music: MusicPlayer,
 }
 
 impl AudioSubsystem {
+    // Falls back to the offline backend when no audio device can be opened
     pub fn new (res: Rc<RefCell<ResourceManager>>) -> Self {
+        Self::with_backend(res, AudioBackend::Device)
+    }
+
+    pub fn with_backend (res: Rc<RefCell<ResourceManager>>, backend: AudioBackend) -> Self {
+        let (mut soloud, backend) = match create_soloud(backend) {
+            Ok(soloud) => (soloud, backend),
+            Err(e) => {
+                log::warn!("Could not open audio backend {:?}: {}, audio will not be heard", backend, e);
+                let offline = AudioBackend::offline();
+                (create_soloud(offline).expect("Could not create offline audio backend!"), offline)
+            }
+        };
+        let spatial = SpatialAudio::new(&mut soloud);
+        let buses: Vec<AudioBus> = DEFAULT_BUSES.iter()
+            .map(|name| AudioBus::new(&soloud, name))
+            .collect();
+
+        let sound = Rc::new(RefCell::new(soloud));
+        let music = MusicPlayer::new(sound.clone(), res.clone(), buses[BusId::MUSIC.0].handle);
+
         Self {
             res,
-            sound: Soloud::default().unwrap(),
+            sound,
+            buses,
+            paused: false,
+            spatial,
+            backend,
+            clock: AudioClock::new(backend),
+            voices: RefCell::new(VoiceLimiter::new()),
+            music,
         }
     }
 
-    pub fn play_sound(&self, sound: &Sound) -> Handle {
+    pub fn play_sound(&self, sound: &Sound) -> SoundInstance {
+        self.play_sound_ex(sound, &PlayParams::default())
+    }
+
+    // Returns an instance that does nothing when a voice limit kept the sound from playing
+    pub fn play_sound_ex(&self, sound: &Sound, params: &PlayParams) -> SoundInstance {
+        if !self.admit_voice(sound, params) {
+            return SoundInstance::rejected(self.sound.clone());
+        }
+
         let res = (*self.res).borrow();
         let sound_data = res.get_sound(sound.id);
-        self.sound.play(&sound_data.wav)
+        let mut soloud = self.sound.borrow_mut();
+
+        // Start paused so every parameter is applied before the first sample is mixed
+        let handle = soloud.play_ex(&sound_data.wav, params.volume, params.pan, true, self.bus_handle(params.bus));
+        AudioSubsystem::apply_params(&mut soloud, handle, params);
+        std::mem::drop(soloud);
+
+        self.track_voice(sound, params, handle);
+        SoundInstance::new(self.sound.clone(), handle)
+    }
+
+    // Plays a sound positioned in the world, panned and attenuated relative to the listener.
+    // The pan of the play params is ignored.
+    pub fn play_sound_at(&mut self, sound: &Sound, position: V2, emitter: &Emitter, params: &PlayParams) -> SoundInstance {
+        if !self.admit_voice(sound, params) {
+            return SoundInstance::rejected(self.sound.clone());
+        }
+
+        let res = (*self.res).borrow();
+        let sound_data = res.get_sound(sound.id);
+        let mut soloud = self.sound.borrow_mut();
+
+        let handle = soloud.play_3d_ex(
+            &sound_data.wav,
+            position.x, position.y, 0.0,
+            0.0, 0.0, 0.0,
+            params.volume,
+            true,
+            self.bus_handle(params.bus),
+        );
+        emitter.apply(&mut soloud, handle);
+        AudioSubsystem::apply_params(&mut soloud, handle, params);
+        std::mem::drop(soloud);
+
+        self.track_voice(sound, params, handle);
+        self.spatial.add_emitter(handle, position);
+        SoundInstance::new(self.sound.clone(), handle)
+    }
+
+    fn admit_voice(&self, sound: &Sound, params: &PlayParams) -> bool {
+        self.voices.borrow_mut().admit(&mut self.sound.borrow_mut(), sound.id, params.priority, self.clock.now())
+    }
+
+    fn track_voice(&self, sound: &Sound, params: &PlayParams, handle: Handle) {
+        self.voices.borrow_mut().track(handle, sound.id, params.priority, self.clock.now());
+    }
+
+    // Caps how many instances of a sound play at once and how often it can be started, None
+    // removes the limit
+    pub fn set_voice_limit(&mut self, sound: &Sound, limit: Option<VoiceLimit>) {
+        self.voices.borrow_mut().set_limit(sound.id, limit);
+    }
+
+    pub fn voice_limit(&self, sound: &Sound) -> Option<VoiceLimit> {
+        self.voices.borrow().limit(sound.id)
+    }
+
+    // Maximum number of sound effect voices playing at once, music does not count towards it.
+    // Once reached, new sounds replace the lowest priority voice or are dropped.
+    pub fn set_max_voices(&mut self, max_voices: Option<u32>) {
+        self.voices.borrow_mut().max_voices = max_voices;
+    }
+
+    pub fn max_voices(&self) -> Option<u32> {
+        self.voices.borrow().max_voices
+    }
+
+    pub fn instance_count(&self, sound: &Sound) -> usize {
+        self.voices.borrow_mut().instance_count(&self.sound.borrow(), sound.id)
+    }
+
+    // Sound effect voices started through the subsystem that are still playing
+    pub fn voice_count(&self) -> usize {
+        self.voices.borrow_mut().voice_count(&self.sound.borrow())
+    }
+
+    fn bus_handle(&self, bus: BusId) -> Handle {
+        self.buses.get(bus.0).unwrap_or(&self.buses[BusId::SFX.0]).handle
+    }
+
+    fn apply_params(soloud: &mut Soloud, handle: Handle, params: &PlayParams) {
+        if params.speed != 1.0 {
+            if let Err(e) = soloud.set_relative_play_speed(handle, params.speed) {
+                log::warn!("Could not set playback speed {}: {}", params.speed, e);
+            }
+        }
+        soloud.set_looping(handle, params.looping);
+        if !params.delay.is_zero() {
+            let samples = params.delay.as_secs_f32() * soloud.backend_samplerate() as f32;
+            soloud.set_delay_samples(handle, samples as u32);
+        }
+        soloud.set_pause(handle, params.paused);
+    }
+
+    pub fn set_emitter_position(&mut self, instance: &SoundInstance, position: V2) {
+        self.spatial.set_emitter_position(instance.handle(), position);
+    }
+
+    // The listener follows the camera unless a position is set, None goes back to the camera
+    pub fn set_listener_position(&mut self, position: Option<V2>) {
+        self.spatial.set_listener(position);
+    }
+
+    pub fn listener_position(&self) -> V2 {
+        self.spatial.listener_position()
+    }
+
+    // Center of the view in world space, updated by the app every frame
+    pub fn set_camera_position(&mut self, position: V2) {
+        self.spatial.set_camera(position);
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.sound.borrow().global_volume()
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.sound.borrow_mut().set_global_volume(volume);
     }
-}
\ No newline at end of file
+
+    // Pauses every voice, voices started while paused play normally
+    pub fn pause_all(&mut self) {
+        self.sound.borrow_mut().set_pause_all(true);
+        self.paused = true;
+    }
+
+    pub fn resume_all(&mut self) {
+        self.sound.borrow_mut().set_pause_all(false);
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn stop_all(&mut self) {
+        let mut soloud = self.sound.borrow_mut();
+        soloud.stop_all();
+
+        // Stopping everything stops the buses as well
+        for bus in self.buses.iter_mut() {
+            bus.handle = soloud.play(&bus.bus);
+        }
+        std::mem::drop(soloud);
+        self.music.stop(Duration::ZERO);
+        self.music.bus = self.buses[BusId::MUSIC.0].handle;
+        self.update_bus_volumes();
+    }
+
+    // Called once per frame by the app
+    pub fn update(&mut self) {
+        self.music.update();
+        self.spatial.update(&mut self.sound.borrow_mut(), self.clock.now());
+    }
+
+    pub fn backend(&self) -> AudioBackend {
+        self.backend
+    }
+
+    // Time the mixer has advanced, only moves forward through `render` on the offline backend
+    pub fn time(&self) -> Duration {
+        self.clock.now()
+    }
+
+    pub fn active_voice_count(&self) -> u32 {
+        self.sound.borrow().active_voice_count()
+    }
+
+    // Mixes the next `duration` of audio into interleaved samples. Only the offline backend can
+    // be rendered, devices are mixed on their own thread and return nothing.
+    pub fn render(&mut self, duration: Duration) -> Vec<f32> {
+        let (sample_rate, channels) = match self.backend {
+            AudioBackend::Offline { sample_rate, channels } => (sample_rate, channels),
+            AudioBackend::Device => {
+                log::warn!("Only the offline audio backend can be rendered");
+                return vec![];
+            }
+        };
+
+        let frames = (duration.as_secs_f64() * sample_rate as f64).round() as u32;
+        let samples = mix_frames(&mut self.sound.borrow_mut(), frames, channels);
+        self.clock.advance(frames as u64);
+
+        samples
+    }
+
+    pub fn render_to_wav(&mut self, duration: Duration, path: &Path) -> std::io::Result<()> {
+        match self.backend {
+            AudioBackend::Offline { sample_rate, channels } => {
+                let samples = self.render(duration);
+                write_wav_file(path, &samples, sample_rate, channels)
+            }
+            AudioBackend::Device => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "only the offline audio backend can be rendered",
+            )),
+        }
+    }
+
+    pub fn add_bus(&mut self, name: &str) -> BusId {
+        if let Some(id) = self.bus_id(name) {
+            return id;
+        }
+
+        let bus = AudioBus::new(&self.sound.borrow(), name);
+        self.buses.push(bus);
+        self.update_bus_volumes();
+        BusId(self.buses.len() - 1)
+    }
+
+    pub fn bus_id(&self, name: &str) -> Option<BusId> {
+        self.buses.iter().position(|bus| bus.name == name).map(BusId)
+    }
+
+    pub fn bus(&self, id: BusId) -> Option<&AudioBus> {
+        self.buses.get(id.0)
+    }
+
+    pub fn buses(&self) -> impl Iterator<Item = (BusId, &AudioBus)> {
+        self.buses.iter().enumerate().map(|(i, bus)| (BusId(i), bus))
+    }
+
+    fn update_bus_settings(&mut self, id: BusId, f: impl FnOnce(&mut BusSettings)) {
+        if let Some(bus) = self.buses.get_mut(id.0) {
+            f(&mut bus.settings);
+            self.update_bus_volumes();
+        }
+    }
+
+    // Solo on any bus silences every bus that isn't soloed
+    fn update_bus_volumes(&mut self) {
+        let any_solo = self.buses.iter().any(|bus| bus.settings.solo);
+        let mut soloud = self.sound.borrow_mut();
+        for bus in self.buses.iter() {
+            soloud.set_volume(bus.handle, bus.effective_volume(any_solo));
+        }
+    }
+
+    pub fn set_bus_volume(&mut self, id: BusId, volume: f32) {
+        self.update_bus_settings(id, |settings| settings.volume = volume.max(0.0));
+    }
+
+    pub fn set_bus_muted(&mut self, id: BusId, muted: bool) {
+        self.update_bus_settings(id, |settings| settings.muted = muted);
+    }
+
+    pub fn set_bus_solo(&mut self, id: BusId, solo: bool) {
+        self.update_bus_settings(id, |settings| settings.solo = solo);
+    }
+
+    // Sounds and buses each have `MAX_FILTERS` filter slots, passing None clears the slot.
+    // Filters on a sound apply to instances played after they are set, and replacing or clearing
+    // one stops the instances still playing with the old filter. soloud builds the filters of an
+    // instance from its sound and bus when it starts, so a filter can't be attached to a single
+    // playing instance. Play it on a bus with the filter instead, its parameters can still be
+    // changed per instance.
+    pub fn set_sound_filter(&self, sound: &Sound, slot: u32, filter: Option<Filter>) -> Result<(), SoloudError> {
+        let mut res = (*self.res).borrow_mut();
+        match res.try_get_sound_mut(sound.id) {
+            Some(sound_data) => {
+                // Playing instances keep pointing at the filter they started with
+                if sound_data.filters.get(slot).is_some() {
+                    sound_data.wav.stop();
+                }
+                sound_data.filters.set(&mut sound_data.wav, slot, filter.as_ref())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn sound_filter(&self, sound: &Sound, slot: u32) -> Option<Filter> {
+        (*self.res).borrow().try_get_sound(sound.id)
+            .and_then(|sound_data| sound_data.filters.get(slot))
+    }
+
+    pub fn set_bus_filter(&mut self, id: BusId, slot: u32, filter: Option<Filter>) -> Result<(), SoloudError> {
+        match self.buses.get_mut(id.0) {
+            Some(bus) => bus.set_filter(slot, filter.as_ref()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_bus_filter_param(&mut self, id: BusId, slot: u32, param: FilterParam, value: f32) {
+        self.fade_bus_filter_param(id, slot, param, value, Duration::ZERO);
+    }
+
+    // Moves a parameter of a bus filter to `value` over `duration`, eg. fading a low pass in
+    pub fn fade_bus_filter_param(&mut self, id: BusId, slot: u32, param: FilterParam, value: f32, duration: Duration) {
+        if let Some(bus) = self.buses.get(id.0) {
+            set_filter_param(&mut self.sound.borrow_mut(), bus.handle, slot, param, value, Some(duration));
+        }
+    }
+
+    pub fn mixer_settings(&self) -> MixerSettings {
+        MixerSettings {
+            master_volume: self.master_volume(),
+            buses: self.buses.iter()
+                .map(|bus| (bus.name.clone(), bus.settings))
+                .collect(),
+        }
+    }
+
+    // Buses missing from the settings are created, buses not mentioned keep their settings
+    pub fn apply_mixer_settings(&mut self, settings: &MixerSettings) {
+        self.set_master_volume(settings.master_volume);
+        for (name, bus_settings) in settings.buses.iter() {
+            let id = self.add_bus(name);
+            self.buses[id.0].settings = *bus_settings;
+        }
+        self.update_bus_volumes();
+    }
+}
@@ -0,0 +1,168 @@
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use soloud::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AudioBackend {
+    // Default output device, mixed on soloud's audio thread
+    Device,
+    // No device is opened. Voices are only mixed when `AudioSubsystem::render` is called and time
+    // only advances by the amount rendered, so playback is fully deterministic.
+    Offline { sample_rate: u32, channels: u32 },
+}
+
+impl AudioBackend {
+    pub fn offline() -> Self {
+        AudioBackend::Offline { sample_rate: 44100, channels: 2 }
+    }
+}
+
+impl Default for AudioBackend {
+    fn default() -> Self {
+        AudioBackend::Device
+    }
+}
+
+pub(crate) fn create_soloud(backend: AudioBackend) -> Result<Soloud, SoloudError> {
+    match backend {
+        AudioBackend::Device => Soloud::default(),
+        AudioBackend::Offline { sample_rate, channels } => {
+            Soloud::new(SoloudFlag::ClipRoundoff, Backend::Null, sample_rate, 0, channels)
+        }
+    }
+}
+
+// Mixes `frames` frames of interleaved audio. Soloud's `mix` wrapper passes the buffer length as
+// the frame count and the mixer then writes `channels` times that, so the ffi call is made
+// directly with the real frame count.
+pub(crate) fn mix_frames(soloud: &mut Soloud, frames: u32, channels: u32) -> Vec<f32> {
+    let mut samples = vec![0.0; frames as usize * channels as usize];
+    if frames > 0 {
+        unsafe { soloud_sys::soloud::Soloud_mix(soloud.inner(), samples.as_mut_ptr(), frames) }
+    }
+    samples
+}
+
+// Wall clock for devices, rendered sample count for the offline backend
+pub(crate) enum AudioClock {
+    RealTime { start: Instant },
+    Virtual { rendered_frames: u64, sample_rate: u32 },
+}
+
+impl AudioClock {
+    pub(crate) fn new (backend: AudioBackend) -> Self {
+        match backend {
+            AudioBackend::Device => AudioClock::RealTime { start: Instant::now() },
+            AudioBackend::Offline { sample_rate, .. } => AudioClock::Virtual { rendered_frames: 0, sample_rate },
+        }
+    }
+
+    pub(crate) fn now(&self) -> Duration {
+        match self {
+            AudioClock::RealTime { start } => start.elapsed(),
+            AudioClock::Virtual { rendered_frames, sample_rate } => {
+                Duration::from_secs_f64(*rendered_frames as f64 / *sample_rate as f64)
+            }
+        }
+    }
+
+    pub(crate) fn advance(&mut self, frames: u64) {
+        if let AudioClock::Virtual { rendered_frames, .. } = self {
+            *rendered_frames += frames;
+        }
+    }
+}
+
+// Writes interleaved float samples as a 16 bit PCM wav file
+pub fn write_wav(writer: &mut impl Write, samples: &[f32], sample_rate: u32, channels: u32) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    let block_align = channels as u16 * 2;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&(channels as u16).to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub fn write_wav_file(path: &Path, samples: &[f32], sample_rate: u32, channels: u32) -> io::Result<()> {
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+    write_wav(&mut file, samples, sample_rate, channels)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f32, sample_rate: u32, seconds: f32) -> Vec<f32> {
+        let count = (sample_rate as f32 * seconds) as usize;
+        (0..count)
+            .map(|i| (i as f32 / sample_rate as f32 * frequency * std::f32::consts::TAU).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut bytes = vec![];
+        write_wav(&mut bytes, &[0.0, 1.0, -1.0, 0.5], 22050, 2).unwrap();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes(bytes[22..24].try_into().unwrap()), 2);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 22050);
+        assert_eq!(u32::from_le_bytes(bytes[28..32].try_into().unwrap()), 22050 * 4);
+        assert_eq!(u16::from_le_bytes(bytes[32..34].try_into().unwrap()), 4);
+        assert_eq!(u16::from_le_bytes(bytes[34..36].try_into().unwrap()), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(i16::from_le_bytes(bytes[46..48].try_into().unwrap()), i16::MAX);
+        assert_eq!(i16::from_le_bytes(bytes[48..50].try_into().unwrap()), -i16::MAX);
+    }
+
+    #[test]
+    fn offline_render_tone() {
+        let (sample_rate, channels) = (44100, 2);
+        let mut soloud = create_soloud(AudioBackend::Offline { sample_rate, channels }).unwrap();
+
+        let mut bytes = vec![];
+        write_wav(&mut bytes, &tone(441.0, sample_rate, 1.0), sample_rate, 1).unwrap();
+        let mut wav = audio::Wav::default();
+        wav.load_mem(&bytes).unwrap();
+        soloud.play(&wav);
+
+        let frames = sample_rate / 10;
+        let samples = mix_frames(&mut soloud, frames, channels);
+        assert_eq!(samples.len(), (frames * channels) as usize);
+
+        // A centered mono source comes out the same on both sides
+        let left: Vec<f32> = samples.chunks(2).map(|frame| frame[0]).collect();
+        assert!(samples.chunks(2).all(|frame| (frame[0] - frame[1]).abs() < 1e-4));
+
+        let peak = left.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak > 0.1, "peak {}", peak);
+
+        // 441 Hz over 0.1s crosses zero twice per period
+        let crossings = left.windows(2).filter(|w| (w[0] < 0.0) != (w[1] < 0.0)).count();
+        assert!((84..=92).contains(&crossings), "{} zero crossings", crossings);
+    }
+}
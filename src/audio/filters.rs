@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use soloud::*;
+
+// soloud supports this many filters per sound and bus
+pub const MAX_FILTERS: u32 = 8;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    // Delay in seconds, decay and filter between 0 and 1
+    Echo { delay: f32, decay: f32, filter: f32 },
+    Reverb { room_size: f32, damp: f32, width: f32 },
+    LowPass { frequency: f32, resonance: f32 },
+    HighPass { frequency: f32, resonance: f32 },
+    BandPass { frequency: f32, resonance: f32 },
+    Flanger { delay: f32, frequency: f32 },
+    BassBoost { boost: f32 },
+    Lofi { sample_rate: f32, bit_depth: f32 },
+}
+
+// Parameters that can be changed or faded while a sound plays. Every filter has `Wet`, the rest
+// only apply to the filters that have them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FilterParam {
+    Wet,
+    Delay,
+    Decay,
+    Frequency,
+    Resonance,
+    RoomSize,
+    Damp,
+    Width,
+    Boost,
+    SampleRate,
+    BitDepth,
+}
+
+pub(crate) enum SoloudFilter {
+    Echo(EchoFilter),
+    Reverb(FreeverbFilter),
+    Biquad(BiquadResonantFilter),
+    Flanger(FlangerFilter),
+    BassBoost(BassboostFilter),
+    Lofi(LofiFilter),
+}
+
+impl SoloudFilter {
+    pub(crate) fn new (filter: &Filter) -> Result<Self, SoloudError> {
+        let biquad = |filter_type, frequency, resonance| -> Result<SoloudFilter, SoloudError> {
+            let mut biquad = BiquadResonantFilter::default();
+            biquad.set_params(filter_type, frequency, resonance)?;
+            Ok(SoloudFilter::Biquad(biquad))
+        };
+
+        Ok(match *filter {
+            Filter::Echo { delay, decay, filter } => {
+                let mut echo = EchoFilter::default();
+                echo.set_params_ex(delay, decay, filter)?;
+                SoloudFilter::Echo(echo)
+            }
+            Filter::Reverb { room_size, damp, width } => {
+                let mut reverb = FreeverbFilter::default();
+                reverb.set_params(0.0, room_size, damp, width)?;
+                SoloudFilter::Reverb(reverb)
+            }
+            Filter::LowPass { frequency, resonance } =>
+                biquad(BiquadResonantFilterType::LowPass, frequency, resonance)?,
+            Filter::HighPass { frequency, resonance } =>
+                biquad(BiquadResonantFilterType::HighPass, frequency, resonance)?,
+            Filter::BandPass { frequency, resonance } =>
+                biquad(BiquadResonantFilterType::BandPass, frequency, resonance)?,
+            Filter::Flanger { delay, frequency } => {
+                let mut flanger = FlangerFilter::default();
+                flanger.set_params(delay, frequency)?;
+                SoloudFilter::Flanger(flanger)
+            }
+            Filter::BassBoost { boost } => {
+                let mut bass_boost = BassboostFilter::default();
+                bass_boost.set_params(boost)?;
+                SoloudFilter::BassBoost(bass_boost)
+            }
+            Filter::Lofi { sample_rate, bit_depth } => {
+                let mut lofi = LofiFilter::default();
+                lofi.set_params(sample_rate, bit_depth)?;
+                SoloudFilter::Lofi(lofi)
+            }
+        })
+    }
+
+    pub(crate) fn attach<A: AudioExt>(&self, source: &mut A, slot: u32) {
+        match self {
+            SoloudFilter::Echo(filter) => source.set_filter(slot, Some(filter)),
+            SoloudFilter::Reverb(filter) => source.set_filter(slot, Some(filter)),
+            SoloudFilter::Biquad(filter) => source.set_filter(slot, Some(filter)),
+            SoloudFilter::Flanger(filter) => source.set_filter(slot, Some(filter)),
+            SoloudFilter::BassBoost(filter) => source.set_filter(slot, Some(filter)),
+            SoloudFilter::Lofi(filter) => source.set_filter(slot, Some(filter)),
+        }
+    }
+}
+
+// soloud only keeps pointers to filters, a source has to own the filters attached to it. Voices
+// of a sound keep reading the filter they started with, so they have to be stopped before its
+// slot is replaced. Buses swap the filter of their running voice themselves.
+pub(crate) struct FilterSlots {
+    slots: Vec<Option<(Filter, SoloudFilter)>>,
+}
+
+impl Default for FilterSlots {
+    fn default() -> Self {
+        Self {
+            slots: (0..MAX_FILTERS).map(|_| None).collect(),
+        }
+    }
+}
+
+impl FilterSlots {
+    pub(crate) fn get(&self, slot: u32) -> Option<Filter> {
+        self.slots.get(slot as usize).and_then(|s| s.as_ref()).map(|(filter, _)| *filter)
+    }
+
+    // Attaches the same filters to a new source, used when a sound is reloaded
+    pub(crate) fn reattach<A: AudioExt>(&self, source: &mut A) {
+        for (slot, entry) in self.slots.iter().enumerate() {
+            if let Some((_, soloud_filter)) = entry {
+                soloud_filter.attach(source, slot as u32);
+            }
+        }
+    }
+
+    pub(crate) fn set<A: AudioExt>(&mut self, source: &mut A, slot: u32, filter: Option<&Filter>) -> Result<(), SoloudError> {
+        if slot >= MAX_FILTERS {
+            return Err(SoloudError::Internal(SoloudErrorKind::InvalidParameter));
+        }
+
+        match filter {
+            Some(filter) => {
+                let soloud_filter = SoloudFilter::new(filter)?;
+                soloud_filter.attach(source, slot);
+                self.slots[slot as usize] = Some((*filter, soloud_filter));
+            }
+            None => {
+                source.set_filter(slot, None::<&EchoFilter>);
+                self.slots[slot as usize] = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Attribute ids are shared between filters that have the same parameter, so the attribute enum
+// of any filter with that parameter addresses it on every filter
+macro_rules! with_filter_attr {
+    ($param:expr, $attr:ident => $body:expr) => {
+        match $param {
+            FilterParam::Wet => { let $attr = EchoFilterAttr::Wet; $body }
+            FilterParam::Delay => { let $attr = EchoFilterAttr::Delay; $body }
+            FilterParam::Decay => { let $attr = EchoFilterAttr::Decay; $body }
+            FilterParam::Frequency => { let $attr = BiquadResonantFilterAttr::Frequency; $body }
+            FilterParam::Resonance => { let $attr = BiquadResonantFilterAttr::Resonance; $body }
+            FilterParam::RoomSize => { let $attr = FreeverbFilterAttr::RoomSize; $body }
+            FilterParam::Damp => { let $attr = FreeverbFilterAttr::Damp; $body }
+            FilterParam::Width => { let $attr = FreeverbFilterAttr::Width; $body }
+            FilterParam::Boost => { let $attr = BassboostFilterAttr::Boost; $body }
+            FilterParam::SampleRate => { let $attr = LofiFilterAttr::Samplerate; $body }
+            FilterParam::BitDepth => { let $attr = LofiFilterAttr::Bitdepth; $body }
+        }
+    };
+}
+
+// Sets the parameter right away, or fades it there over `fade` when one is given
+pub(crate) fn set_filter_param(
+    soloud: &mut Soloud,
+    handle: Handle,
+    slot: u32,
+    param: FilterParam,
+    value: f32,
+    fade: Option<Duration>,
+) {
+    match fade {
+        Some(fade) if !fade.is_zero() => with_filter_attr!(param, attr =>
+            soloud.fade_filter_param(handle, slot, attr, value, fade.as_secs_f64())),
+        _ => with_filter_attr!(param, attr =>
+            soloud.set_filter_param(handle, slot, attr, value)),
+    }
+}
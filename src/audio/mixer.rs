@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use soloud::*;
+
+use crate::audio::filters::{Filter, FilterSlots};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BusId(pub(crate) usize);
+
+impl BusId {
+    // Buses every `AudioSubsystem` starts with
+    pub const MUSIC: BusId = BusId(0);
+    pub const SFX: BusId = BusId(1);
+    pub const VOICE: BusId = BusId(2);
+    pub const UI: BusId = BusId(3);
+}
+
+pub(crate) const DEFAULT_BUSES: [&str; 4] = ["music", "sfx", "voice", "ui"];
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct BusSettings {
+    pub volume: f32,
+    pub muted: bool,
+    pub solo: bool,
+}
+
+impl Default for BusSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+            solo: false,
+        }
+    }
+}
+
+// Everything an options menu needs to save and restore, buses are stored by name
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct MixerSettings {
+    pub master_volume: f32,
+    pub buses: BTreeMap<String, BusSettings>,
+}
+
+impl Default for MixerSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            buses: BTreeMap::new(),
+        }
+    }
+}
+
+pub struct AudioBus {
+    pub(crate) name: String,
+    pub(crate) bus: Bus,
+    // Voice handle of the bus itself, sounds are routed to the bus by playing them on it
+    pub(crate) handle: Handle,
+    pub(crate) settings: BusSettings,
+    filters: FilterSlots,
+}
+
+impl AudioBus {
+    pub(crate) fn new (soloud: &Soloud, name: &str) -> Self {
+        let bus = Bus::default();
+        let handle = soloud.play(&bus);
+
+        Self {
+            name: name.to_string(),
+            bus,
+            handle,
+            settings: BusSettings::default(),
+            filters: FilterSlots::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn settings(&self) -> BusSettings {
+        self.settings
+    }
+
+    // Volume the bus is mixed at once mute and solo are taken into account
+    pub(crate) fn effective_volume(&self, any_solo: bool) -> f32 {
+        if self.settings.muted || (any_solo && !self.settings.solo) {
+            0.0
+        } else {
+            self.settings.volume
+        }
+    }
+
+    pub fn filter(&self, slot: u32) -> Option<Filter> {
+        self.filters.get(slot)
+    }
+
+    // Bus filters also apply to the bus that is already playing
+    pub(crate) fn set_filter(&mut self, slot: u32, filter: Option<&Filter>) -> Result<(), SoloudError> {
+        self.filters.set(&mut self.bus, slot, filter)
+    }
+}
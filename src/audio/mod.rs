@@ -1 +1,9 @@
-pub mod audio_subsystem;
\ No newline at end of file
+pub mod audio_subsystem;
+pub mod playback;
+pub mod mixer;
+pub mod music;
+pub mod spatial;
+pub mod backend;
+pub mod filters;
+pub mod sfx;
+pub mod voices;
@@ -0,0 +1,332 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use soloud::*;
+
+use crate::math::rng::Rng;
+use crate::sys::handle::ResourceId;
+use crate::sys::resource_manager::{MusicID, ResourceManager};
+
+// Music is decoded while it plays instead of up front. Files on disk are streamed straight from
+// the file, anything else keeps only the encoded bytes in memory.
+pub struct MusicData {
+    pub id: MusicID,
+    pub stream: WavStream,
+}
+
+impl MusicData {
+    pub fn from_file (path: &Path) -> Result<Self, SoloudError> {
+        let mut stream = WavStream::default();
+        stream.load(path)?;
+
+        Ok(Self {
+            id: ResourceId::invalid(),
+            stream,
+        })
+    }
+
+    pub fn from_bytes (bytes: &[u8]) -> Result<Self, SoloudError> {
+        let mut stream = WavStream::default();
+        stream.load_mem(bytes)?;
+
+        Ok(Self {
+            id: ResourceId::invalid(),
+            stream,
+        })
+    }
+
+    pub fn length(&self) -> Duration {
+        Duration::from_secs_f64(self.stream.length().max(0.0))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Track {
+    pub music: MusicID,
+    // Only used outside of playlists
+    pub looping: bool,
+    // Where playback jumps back to when a looping track reaches its end
+    pub loop_start: Duration,
+}
+
+impl Track {
+    pub fn new (music: MusicID) -> Self {
+        Self {
+            music,
+            looping: true,
+            loop_start: Duration::ZERO,
+        }
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_loop_start(mut self, loop_start: Duration) -> Self {
+        self.loop_start = loop_start;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Playlist {
+    pub tracks: Vec<Track>,
+    pub shuffle: bool,
+    pub repeat: bool,
+    // Overlap between consecutive tracks
+    pub crossfade: Duration,
+}
+
+impl Playlist {
+    pub fn new (tracks: Vec<Track>) -> Self {
+        Self {
+            tracks,
+            repeat: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn with_crossfade(mut self, crossfade: Duration) -> Self {
+        self.crossfade = crossfade;
+        self
+    }
+}
+
+struct PlaylistState {
+    playlist: Playlist,
+    order: Vec<usize>,
+    position: usize,
+}
+
+struct Voice {
+    handle: Handle,
+    track: Track,
+}
+
+// Plays one music track at a time on the music bus, owned by `AudioSubsystem`
+pub struct MusicPlayer {
+    soloud: Rc<RefCell<Soloud>>,
+    res: Rc<RefCell<ResourceManager>>,
+    pub(crate) bus: Handle,
+
+    volume: f32,
+    current: Option<Voice>,
+    playlist: Option<PlaylistState>,
+    rng: Rng,
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs_f64()
+}
+
+impl MusicPlayer {
+    pub(crate) fn new (soloud: Rc<RefCell<Soloud>>, res: Rc<RefCell<ResourceManager>>, bus: Handle) -> Self {
+        Self {
+            soloud,
+            res,
+            bus,
+            volume: 1.0,
+            current: None,
+            playlist: None,
+            rng: Rng::from_time(),
+        }
+    }
+
+    fn start_voice(&self, track: Track, fade_in: Duration) -> Option<Voice> {
+        let res = (*self.res).borrow();
+        let music = match res.try_get_music(track.music) {
+            Some(music) => music,
+            None => {
+                log::warn!("Can't play {:?}, it is not loaded", track.music);
+                return None;
+            }
+        };
+
+        let mut soloud = self.soloud.borrow_mut();
+        let start_volume = if fade_in.is_zero() { self.volume } else { 0.0 };
+        let handle = soloud.play_ex(&music.stream, start_volume, 0.0, true, self.bus);
+
+        let looping = track.looping && self.playlist.is_none();
+        soloud.set_looping(handle, looping);
+        if looping && !track.loop_start.is_zero() {
+            soloud.set_loop_point(handle, secs(track.loop_start));
+        }
+        // Music should never be stolen for a sound effect
+        soloud.set_protect_voice(handle, true);
+        if !fade_in.is_zero() {
+            soloud.fade_volume(handle, self.volume, secs(fade_in));
+        }
+        soloud.set_pause(handle, false);
+
+        Some(Voice { handle, track })
+    }
+
+    fn fade_out_voice(&self, voice: &Voice, fade_out: Duration) {
+        let mut soloud = self.soloud.borrow_mut();
+        if fade_out.is_zero() {
+            soloud.stop(voice.handle);
+        } else {
+            soloud.fade_volume(voice.handle, 0.0, secs(fade_out));
+            soloud.schedule_stop(voice.handle, secs(fade_out));
+        }
+    }
+
+    pub fn play(&mut self, track: Track, fade_in: Duration) {
+        self.playlist = None;
+        self.stop(Duration::ZERO);
+        self.current = self.start_voice(track, fade_in);
+    }
+
+    // Fades the current track out while the new one fades in
+    pub fn crossfade_to(&mut self, track: Track, duration: Duration) {
+        self.playlist = None;
+        self.crossfade(track, duration);
+    }
+
+    fn crossfade(&mut self, track: Track, duration: Duration) {
+        if let Some(voice) = self.current.take() {
+            self.fade_out_voice(&voice, duration);
+        }
+        self.current = self.start_voice(track, duration);
+    }
+
+    pub fn stop(&mut self, fade_out: Duration) {
+        if let Some(voice) = self.current.take() {
+            self.fade_out_voice(&voice, fade_out);
+        }
+    }
+
+    pub fn stop_playlist(&mut self, fade_out: Duration) {
+        self.playlist = None;
+        self.stop(fade_out);
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(voice) = &self.current {
+            self.soloud.borrow_mut().set_pause(voice.handle, true);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(voice) = &self.current {
+            self.soloud.borrow_mut().set_pause(voice.handle, false);
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.current.as_ref()
+            .map_or(false, |voice| self.soloud.borrow().is_valid_voice_handle(voice.handle))
+    }
+
+    pub fn current_track(&self) -> Option<Track> {
+        self.current.as_ref().map(|voice| voice.track)
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0);
+        if let Some(voice) = &self.current {
+            self.soloud.borrow_mut().set_volume(voice.handle, self.volume);
+        }
+    }
+
+    pub fn play_playlist(&mut self, playlist: Playlist, fade_in: Duration) {
+        self.stop(Duration::ZERO);
+        if playlist.tracks.is_empty() {
+            self.playlist = None;
+            return;
+        }
+
+        let mut order: Vec<usize> = (0..playlist.tracks.len()).collect();
+        if playlist.shuffle {
+            self.rng.shuffle(&mut order);
+        }
+
+        let track = playlist.tracks[order[0]];
+        self.playlist = Some(PlaylistState { playlist, order, position: 0 });
+        self.current = self.start_voice(track, fade_in);
+    }
+
+    fn next_track(&mut self) -> Option<Track> {
+        let state = self.playlist.as_mut()?;
+        state.position += 1;
+
+        if state.position >= state.order.len() {
+            if !state.playlist.repeat { return None; }
+
+            state.position = 0;
+            if state.playlist.shuffle {
+                let last = state.order.last().copied();
+                self.rng.shuffle(&mut state.order);
+                // Avoid playing the same track twice in a row across reshuffles
+                if state.order.len() > 1 && state.order.first().copied() == last {
+                    state.order.swap(0, 1);
+                }
+            }
+        }
+
+        Some(state.playlist.tracks[state.order[state.position]])
+    }
+
+    // Skips to the next track of the playlist
+    pub fn next(&mut self, crossfade: Duration) {
+        match self.next_track() {
+            Some(track) => self.crossfade(track, crossfade),
+            None => self.stop_playlist(crossfade),
+        }
+    }
+
+    // Advances playlists, called once per frame by `AudioSubsystem::update`
+    pub(crate) fn update(&mut self) {
+        let crossfade = match &self.playlist {
+            Some(state) => state.playlist.crossfade,
+            None => return,
+        };
+
+        let (handle, music) = match &self.current {
+            Some(voice) => (voice.handle, voice.track.music),
+            None => return,
+        };
+
+        let finished = !self.soloud.borrow().is_valid_voice_handle(handle);
+        if finished {
+            self.current = None;
+            match self.next_track() {
+                Some(track) => self.current = self.start_voice(track, Duration::ZERO),
+                None => self.playlist = None,
+            }
+            return;
+        }
+
+        if crossfade.is_zero() { return; }
+
+        let length = match (*self.res).borrow().try_get_music(music) {
+            Some(music) => music.length(),
+            None => return,
+        };
+        if length.is_zero() { return; }
+        // A track shorter than the crossfade would otherwise be skipped as soon as it starts
+        let crossfade = crossfade.min(length / 2);
+        let position = Duration::from_secs_f64(self.soloud.borrow().stream_position(handle).max(0.0));
+        // Start the next track early so the two overlap
+        if position + crossfade >= length {
+            self.next(crossfade);
+        }
+    }
+}
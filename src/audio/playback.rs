@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use soloud::*;
+
+use crate::audio::filters::{FilterParam, set_filter_param};
+use crate::audio::mixer::BusId;
+
+#[derive(Copy, Clone, Debug)]
+pub struct PlayParams {
+    pub volume: f32,
+    // -1.0 is fully left, 1.0 fully right
+    pub pan: f32,
+    // Relative playback speed, also shifts the pitch
+    pub speed: f32,
+    pub looping: bool,
+    pub paused: bool,
+    pub delay: Duration,
+    pub bus: BusId,
+    // Voices with a higher priority are kept when the global voice cap is reached
+    pub priority: i32,
+}
+
+impl Default for PlayParams {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pan: 0.0,
+            speed: 1.0,
+            looping: false,
+            paused: false,
+            delay: Duration::ZERO,
+            bus: BusId::SFX,
+            priority: 0,
+        }
+    }
+}
+
+impl PlayParams {
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn with_pan(mut self, pan: f32) -> Self {
+        self.pan = pan.clamp(-1.0, 1.0);
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_bus(mut self, bus: BusId) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+// A playing voice. Once the sound has finished or was stopped the instance stays valid to use,
+// every call just does nothing.
+#[derive(Clone)]
+pub struct SoundInstance {
+    soloud: Rc<RefCell<Soloud>>,
+    handle: Handle,
+}
+
+impl SoundInstance {
+    pub(crate) fn new (soloud: Rc<RefCell<Soloud>>, handle: Handle) -> Self {
+        Self { soloud, handle }
+    }
+
+    // Returned when a voice limit kept the sound from playing. Handle 0 never belongs to a voice.
+    pub(crate) fn rejected (soloud: Rc<RefCell<Soloud>>) -> Self {
+        Self { soloud, handle: Handle::PRIMARY }
+    }
+
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.soloud.borrow().is_valid_voice_handle(self.handle)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_alive() && !self.is_paused()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.soloud.borrow().pause(self.handle)
+    }
+
+    pub fn stop(&self) {
+        self.soloud.borrow_mut().stop(self.handle);
+    }
+
+    pub fn pause(&self) {
+        self.soloud.borrow_mut().set_pause(self.handle, true);
+    }
+
+    pub fn resume(&self) {
+        self.soloud.borrow_mut().set_pause(self.handle, false);
+    }
+
+    pub fn seek(&self, position: Duration) -> Result<(), SoloudError> {
+        self.soloud.borrow_mut().seek(self.handle, position.as_secs_f64())
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.soloud.borrow().stream_position(self.handle).max(0.0))
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.soloud.borrow().volume(self.handle)
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.soloud.borrow_mut().set_volume(self.handle, volume);
+    }
+
+    pub fn set_pan(&self, pan: f32) {
+        self.soloud.borrow_mut().set_pan(self.handle, pan.clamp(-1.0, 1.0));
+    }
+
+    pub fn set_speed(&self, speed: f32) -> Result<(), SoloudError> {
+        self.soloud.borrow_mut().set_relative_play_speed(self.handle, speed)
+    }
+
+    pub fn set_looping(&self, looping: bool) {
+        self.soloud.borrow_mut().set_looping(self.handle, looping);
+    }
+
+    // Only filters attached to the sound or bus before the instance started can be changed
+    pub fn set_filter_param(&self, slot: u32, param: FilterParam, value: f32) {
+        set_filter_param(&mut self.soloud.borrow_mut(), self.handle, slot, param, value, None);
+    }
+
+    pub fn fade_filter_param(&self, slot: u32, param: FilterParam, value: f32, duration: Duration) {
+        set_filter_param(&mut self.soloud.borrow_mut(), self.handle, slot, param, value, Some(duration));
+    }
+}
@@ -0,0 +1,598 @@
+use std::f32::consts::PI;
+
+use crate::audio::backend::write_wav;
+use crate::math::rng::Rng;
+
+// Port of DrPetter's sfxr synthesizer. Parameters are in sfxr's normalized ranges, 0..1 or -1..1
+// for ramps and offsets.
+
+pub const SFX_SAMPLE_RATE: u32 = 44100;
+
+const MASTER_VOLUME: f32 = 0.05;
+const SUPERSAMPLES: usize = 8;
+const PHASER_SIZE: usize = 1024;
+const NOISE_SIZE: usize = 32;
+// Guards against presets that would never end
+const MAX_SAMPLES: usize = SFX_SAMPLE_RATE as usize * 10;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Waveform {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SfxPreset {
+    Coin,
+    Laser,
+    Explosion,
+    Powerup,
+    Hit,
+    Jump,
+    Blip,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SfxParams {
+    pub waveform: Waveform,
+    pub volume: f32,
+
+    pub base_freq: f32,
+    pub freq_limit: f32,
+    pub freq_ramp: f32,
+    pub freq_delta_ramp: f32,
+
+    pub vibrato_strength: f32,
+    pub vibrato_speed: f32,
+
+    pub arp_mod: f32,
+    pub arp_speed: f32,
+
+    pub duty: f32,
+    pub duty_ramp: f32,
+
+    pub repeat_speed: f32,
+
+    pub env_attack: f32,
+    pub env_sustain: f32,
+    pub env_punch: f32,
+    pub env_decay: f32,
+
+    pub lpf_freq: f32,
+    pub lpf_ramp: f32,
+    pub lpf_resonance: f32,
+    pub hpf_freq: f32,
+    pub hpf_ramp: f32,
+
+    pub phaser_offset: f32,
+    pub phaser_ramp: f32,
+}
+
+impl Default for SfxParams {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            volume: 0.5,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_delta_ramp: 0.0,
+            vibrato_strength: 0.0,
+            vibrato_speed: 0.0,
+            arp_mod: 0.0,
+            arp_speed: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            repeat_speed: 0.0,
+            env_attack: 0.0,
+            env_sustain: 0.3,
+            env_punch: 0.0,
+            env_decay: 0.4,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            lpf_resonance: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            phaser_offset: 0.0,
+            phaser_ramp: 0.0,
+        }
+    }
+}
+
+// sfxr's rnd(n) is inclusive
+fn rnd(rng: &mut Rng, n: usize) -> usize {
+    rng.below(n + 1)
+}
+
+fn frnd(rng: &mut Rng, range: f32) -> f32 {
+    rng.range_f32(0.0, range)
+}
+
+impl SfxParams {
+    // The same preset and seed always give the same sound
+    pub fn preset(preset: SfxPreset, seed: u64) -> Self {
+        Self::preset_with_rng(preset, &mut Rng::new(seed))
+    }
+
+    pub fn preset_with_rng(preset: SfxPreset, rng: &mut Rng) -> Self {
+        let mut p = SfxParams::default();
+
+        match preset {
+            SfxPreset::Coin => {
+                p.base_freq = 0.4 + frnd(rng, 0.5);
+                p.env_attack = 0.0;
+                p.env_sustain = frnd(rng, 0.1);
+                p.env_decay = 0.1 + frnd(rng, 0.4);
+                p.env_punch = 0.3 + frnd(rng, 0.3);
+                if rnd(rng, 1) == 1 {
+                    p.arp_speed = 0.5 + frnd(rng, 0.2);
+                    p.arp_mod = 0.2 + frnd(rng, 0.4);
+                }
+            }
+            SfxPreset::Laser => {
+                let mut wave = rnd(rng, 2);
+                if wave == 2 && rnd(rng, 1) == 1 {
+                    wave = rnd(rng, 1);
+                }
+                p.waveform = [Waveform::Square, Waveform::Sawtooth, Waveform::Sine][wave];
+                p.base_freq = 0.5 + frnd(rng, 0.5);
+                p.freq_limit = (p.base_freq - 0.2 - frnd(rng, 0.6)).max(0.2);
+                p.freq_ramp = -0.15 - frnd(rng, 0.2);
+                if rnd(rng, 2) == 0 {
+                    p.base_freq = 0.3 + frnd(rng, 0.6);
+                    p.freq_limit = frnd(rng, 0.1);
+                    p.freq_ramp = -0.35 - frnd(rng, 0.3);
+                }
+                if rnd(rng, 1) == 1 {
+                    p.duty = frnd(rng, 0.5);
+                    p.duty_ramp = frnd(rng, 0.2);
+                } else {
+                    p.duty = 0.4 + frnd(rng, 0.5);
+                    p.duty_ramp = -frnd(rng, 0.7);
+                }
+                p.env_attack = 0.0;
+                p.env_sustain = 0.1 + frnd(rng, 0.2);
+                p.env_decay = frnd(rng, 0.4);
+                if rnd(rng, 1) == 1 {
+                    p.env_punch = frnd(rng, 0.3);
+                }
+                if rnd(rng, 2) == 0 {
+                    p.phaser_offset = frnd(rng, 0.2);
+                    p.phaser_ramp = -frnd(rng, 0.2);
+                }
+                if rnd(rng, 1) == 1 {
+                    p.hpf_freq = frnd(rng, 0.3);
+                }
+            }
+            SfxPreset::Explosion => {
+                p.waveform = Waveform::Noise;
+                if rnd(rng, 1) == 1 {
+                    p.base_freq = 0.1 + frnd(rng, 0.4);
+                    p.freq_ramp = -0.1 + frnd(rng, 0.4);
+                } else {
+                    p.base_freq = 0.2 + frnd(rng, 0.7);
+                    p.freq_ramp = -0.2 - frnd(rng, 0.2);
+                }
+                p.base_freq *= p.base_freq;
+                if rnd(rng, 4) == 0 {
+                    p.freq_ramp = 0.0;
+                }
+                if rnd(rng, 2) == 0 {
+                    p.repeat_speed = 0.3 + frnd(rng, 0.5);
+                }
+                p.env_attack = 0.0;
+                p.env_sustain = 0.1 + frnd(rng, 0.3);
+                p.env_decay = frnd(rng, 0.5);
+                if rnd(rng, 1) == 0 {
+                    p.phaser_offset = -0.3 + frnd(rng, 0.9);
+                    p.phaser_ramp = -frnd(rng, 0.3);
+                }
+                p.env_punch = 0.2 + frnd(rng, 0.6);
+                if rnd(rng, 1) == 1 {
+                    p.vibrato_strength = frnd(rng, 0.7);
+                    p.vibrato_speed = frnd(rng, 0.6);
+                }
+                if rnd(rng, 2) == 0 {
+                    p.arp_speed = 0.6 + frnd(rng, 0.3);
+                    p.arp_mod = 0.8 - frnd(rng, 1.6);
+                }
+            }
+            SfxPreset::Powerup => {
+                if rnd(rng, 1) == 1 {
+                    p.waveform = Waveform::Sawtooth;
+                } else {
+                    p.duty = frnd(rng, 0.6);
+                }
+                if rnd(rng, 1) == 1 {
+                    p.base_freq = 0.2 + frnd(rng, 0.3);
+                    p.freq_ramp = 0.1 + frnd(rng, 0.4);
+                    p.repeat_speed = 0.4 + frnd(rng, 0.4);
+                } else {
+                    p.base_freq = 0.2 + frnd(rng, 0.3);
+                    p.freq_ramp = 0.05 + frnd(rng, 0.2);
+                    if rnd(rng, 1) == 1 {
+                        p.vibrato_strength = frnd(rng, 0.7);
+                        p.vibrato_speed = frnd(rng, 0.6);
+                    }
+                }
+                p.env_attack = 0.0;
+                p.env_sustain = frnd(rng, 0.4);
+                p.env_decay = 0.1 + frnd(rng, 0.4);
+            }
+            SfxPreset::Hit => {
+                p.waveform = match rnd(rng, 2) {
+                    0 => Waveform::Square,
+                    1 => Waveform::Sawtooth,
+                    _ => Waveform::Noise,
+                };
+                if p.waveform == Waveform::Square {
+                    p.duty = frnd(rng, 0.6);
+                }
+                p.base_freq = 0.2 + frnd(rng, 0.6);
+                p.freq_ramp = -0.3 - frnd(rng, 0.4);
+                p.env_attack = 0.0;
+                p.env_sustain = frnd(rng, 0.1);
+                p.env_decay = 0.1 + frnd(rng, 0.2);
+                if rnd(rng, 1) == 1 {
+                    p.hpf_freq = frnd(rng, 0.3);
+                }
+            }
+            SfxPreset::Jump => {
+                p.waveform = Waveform::Square;
+                p.duty = frnd(rng, 0.6);
+                p.base_freq = 0.3 + frnd(rng, 0.3);
+                p.freq_ramp = 0.1 + frnd(rng, 0.2);
+                p.env_attack = 0.0;
+                p.env_sustain = 0.1 + frnd(rng, 0.3);
+                p.env_decay = 0.1 + frnd(rng, 0.2);
+                if rnd(rng, 1) == 1 {
+                    p.hpf_freq = frnd(rng, 0.3);
+                }
+                if rnd(rng, 1) == 1 {
+                    p.lpf_freq = 1.0 - frnd(rng, 0.6);
+                }
+            }
+            SfxPreset::Blip => {
+                p.waveform = if rnd(rng, 1) == 0 { Waveform::Square } else { Waveform::Sawtooth };
+                if p.waveform == Waveform::Square {
+                    p.duty = frnd(rng, 0.6);
+                }
+                p.base_freq = 0.2 + frnd(rng, 0.4);
+                p.env_attack = 0.0;
+                p.env_sustain = 0.1 + frnd(rng, 0.1);
+                p.env_decay = frnd(rng, 0.2);
+                p.hpf_freq = 0.1;
+            }
+        }
+
+        p
+    }
+
+    // Nudges every parameter a little, like sfxr's mutate button
+    pub fn mutate(&mut self, rng: &mut Rng) {
+        let mut nudge = |value: &mut f32, min: f32| {
+            if rnd(rng, 1) == 1 {
+                *value = (*value + frnd(rng, 0.1) - 0.05).clamp(min, 1.0);
+            }
+        };
+
+        nudge(&mut self.base_freq, 0.0);
+        nudge(&mut self.freq_ramp, -1.0);
+        nudge(&mut self.freq_delta_ramp, -1.0);
+        nudge(&mut self.duty, 0.0);
+        nudge(&mut self.duty_ramp, -1.0);
+        nudge(&mut self.vibrato_strength, 0.0);
+        nudge(&mut self.vibrato_speed, 0.0);
+        nudge(&mut self.env_attack, 0.0);
+        nudge(&mut self.env_sustain, 0.0);
+        nudge(&mut self.env_decay, 0.0);
+        nudge(&mut self.env_punch, 0.0);
+        nudge(&mut self.lpf_resonance, 0.0);
+        nudge(&mut self.lpf_freq, 0.0);
+        nudge(&mut self.lpf_ramp, -1.0);
+        nudge(&mut self.hpf_freq, 0.0);
+        nudge(&mut self.hpf_ramp, -1.0);
+        nudge(&mut self.phaser_offset, -1.0);
+        nudge(&mut self.phaser_ramp, -1.0);
+        nudge(&mut self.repeat_speed, 0.0);
+        nudge(&mut self.arp_speed, 0.0);
+        nudge(&mut self.arp_mod, -1.0);
+    }
+
+    // Mono samples at `SFX_SAMPLE_RATE`. Noise is drawn from the given rng so the output is
+    // deterministic for a given seed.
+    pub fn synthesize(&self, rng: &mut Rng) -> Vec<f32> {
+        let mut synth = Synth::new(self, rng);
+        let mut samples = vec![];
+        while synth.playing && samples.len() < MAX_SAMPLES {
+            samples.push(synth.sample(self, rng));
+        }
+        samples
+    }
+
+    // 16 bit mono wav, ready for `ResourceManager::add_sound_from_bytes`
+    pub fn to_wav(&self, seed: u64) -> Vec<u8> {
+        let samples = self.synthesize(&mut Rng::new(seed));
+        let mut bytes = Vec::with_capacity(44 + samples.len() * 2);
+        write_wav(&mut bytes, &samples, SFX_SAMPLE_RATE, 1).unwrap();
+        bytes
+    }
+}
+
+struct Synth {
+    playing: bool,
+
+    phase: usize,
+    period: f64,
+    max_period: f64,
+    slide: f64,
+    delta_slide: f64,
+    square_duty: f32,
+    square_slide: f32,
+    arp_mod: f64,
+    arp_time: usize,
+    arp_limit: usize,
+
+    env_stage: usize,
+    env_time: usize,
+    env_length: [usize; 3],
+    env_vol: f32,
+
+    flt_p: f32,
+    flt_dp: f32,
+    flt_w: f32,
+    flt_w_d: f32,
+    flt_dmp: f32,
+    flt_php: f32,
+    flt_hp: f32,
+    flt_hp_d: f32,
+
+    vib_phase: f32,
+    vib_speed: f32,
+    vib_amp: f32,
+
+    phaser_phase: f32,
+    phaser_delta: f32,
+    phaser_offset: usize,
+    phaser_pos: usize,
+    phaser_buffer: [f32; PHASER_SIZE],
+    noise_buffer: [f32; NOISE_SIZE],
+
+    rep_time: usize,
+    rep_limit: usize,
+}
+
+impl Synth {
+    fn new (p: &SfxParams, rng: &mut Rng) -> Self {
+        let mut synth = Synth {
+            playing: true,
+            phase: 0,
+            period: 0.0,
+            max_period: 0.0,
+            slide: 0.0,
+            delta_slide: 0.0,
+            square_duty: 0.0,
+            square_slide: 0.0,
+            arp_mod: 0.0,
+            arp_time: 0,
+            arp_limit: 0,
+            env_stage: 0,
+            env_time: 0,
+            env_length: [
+                (p.env_attack * p.env_attack * 100000.0) as usize,
+                (p.env_sustain * p.env_sustain * 100000.0) as usize,
+                (p.env_decay * p.env_decay * 100000.0) as usize,
+            ],
+            env_vol: 0.0,
+            flt_p: 0.0,
+            flt_dp: 0.0,
+            flt_w: p.lpf_freq.powi(3) * 0.1,
+            flt_w_d: 1.0 + p.lpf_ramp * 0.0001,
+            flt_dmp: (5.0 / (1.0 + p.lpf_resonance.powi(2) * 20.0) * (0.01 + p.lpf_freq.powi(3) * 0.1)).min(0.8),
+            flt_php: 0.0,
+            flt_hp: p.hpf_freq.powi(2) * 0.1,
+            flt_hp_d: 1.0 + p.hpf_ramp * 0.0003,
+            vib_phase: 0.0,
+            vib_speed: p.vibrato_speed.powi(2) * 0.01,
+            vib_amp: p.vibrato_strength * 0.5,
+            phaser_phase: p.phaser_offset.powi(2) * 1020.0 * p.phaser_offset.signum(),
+            phaser_delta: p.phaser_ramp.powi(2) * p.phaser_ramp.signum(),
+            phaser_offset: 0,
+            phaser_pos: 0,
+            phaser_buffer: [0.0; PHASER_SIZE],
+            noise_buffer: [0.0; NOISE_SIZE],
+            rep_time: 0,
+            rep_limit: if p.repeat_speed == 0.0 { 0 } else { ((1.0 - p.repeat_speed).powi(2) * 20000.0 + 32.0) as usize },
+        };
+
+        synth.phaser_offset = (synth.phaser_phase.abs() as usize).min(PHASER_SIZE - 1);
+        synth.fill_noise(rng);
+        synth.reset_pitch(p);
+        synth
+    }
+
+    fn fill_noise(&mut self, rng: &mut Rng) {
+        for noise in self.noise_buffer.iter_mut() {
+            *noise = rng.range_f32(-1.0, 1.0);
+        }
+    }
+
+    // Also used to restart the pitch when the sound repeats
+    fn reset_pitch(&mut self, p: &SfxParams) {
+        self.period = 100.0 / (p.base_freq as f64 * p.base_freq as f64 + 0.001);
+        self.max_period = 100.0 / (p.freq_limit as f64 * p.freq_limit as f64 + 0.001);
+        self.slide = 1.0 - (p.freq_ramp as f64).powi(3) * 0.01;
+        self.delta_slide = -(p.freq_delta_ramp as f64).powi(3) * 0.000001;
+        self.square_duty = 0.5 - p.duty * 0.5;
+        self.square_slide = -p.duty_ramp * 0.00005;
+        self.arp_mod = if p.arp_mod >= 0.0 {
+            1.0 - (p.arp_mod as f64).powi(2) * 0.9
+        } else {
+            1.0 + (p.arp_mod as f64).powi(2) * 10.0
+        };
+        self.arp_time = 0;
+        self.arp_limit = if p.arp_speed == 1.0 { 0 } else { ((1.0 - p.arp_speed).powi(2) * 20000.0 + 32.0) as usize };
+    }
+
+    fn sample(&mut self, p: &SfxParams, rng: &mut Rng) -> f32 {
+        self.rep_time += 1;
+        if self.rep_limit != 0 && self.rep_time >= self.rep_limit {
+            self.rep_time = 0;
+            self.reset_pitch(p);
+        }
+
+        self.arp_time += 1;
+        if self.arp_limit != 0 && self.arp_time >= self.arp_limit {
+            self.arp_limit = 0;
+            self.period *= self.arp_mod;
+        }
+
+        self.slide += self.delta_slide;
+        self.period *= self.slide;
+        if self.period > self.max_period {
+            self.period = self.max_period;
+            if p.freq_limit > 0.0 {
+                self.playing = false;
+            }
+        }
+
+        let mut vibrato_period = self.period;
+        if self.vib_amp > 0.0 {
+            self.vib_phase += self.vib_speed;
+            vibrato_period = self.period * (1.0 + self.vib_phase.sin() as f64 * self.vib_amp as f64);
+        }
+        let period = (vibrato_period as usize).max(8);
+
+        self.square_duty = (self.square_duty + self.square_slide).clamp(0.0, 0.5);
+
+        self.env_time += 1;
+        if self.env_time > self.env_length[self.env_stage] {
+            self.env_time = 0;
+            self.env_stage += 1;
+            if self.env_stage == 3 {
+                self.playing = false;
+                return 0.0;
+            }
+        }
+        let stage_progress = self.env_time as f32 / self.env_length[self.env_stage].max(1) as f32;
+        self.env_vol = match self.env_stage {
+            0 => stage_progress,
+            1 => 1.0 + (1.0 - stage_progress) * 2.0 * p.env_punch,
+            _ => 1.0 - stage_progress,
+        };
+
+        self.phaser_phase += self.phaser_delta;
+        self.phaser_offset = (self.phaser_phase.abs() as usize).min(PHASER_SIZE - 1);
+
+        if self.flt_hp_d != 0.0 {
+            self.flt_hp = (self.flt_hp * self.flt_hp_d).clamp(0.00001, 0.1);
+        }
+
+        let mut super_sample = 0.0;
+        for _ in 0..SUPERSAMPLES {
+            self.phase += 1;
+            if self.phase >= period {
+                self.phase %= period;
+                if p.waveform == Waveform::Noise {
+                    self.fill_noise(rng);
+                }
+            }
+
+            let fp = self.phase as f32 / period as f32;
+            let mut sample = match p.waveform {
+                Waveform::Square => if fp < self.square_duty { 0.5 } else { -0.5 },
+                Waveform::Sawtooth => 1.0 - fp * 2.0,
+                Waveform::Sine => (fp * 2.0 * PI).sin(),
+                Waveform::Noise => self.noise_buffer[self.phase * NOISE_SIZE / period],
+            };
+
+            // Low pass
+            let previous = self.flt_p;
+            self.flt_w = (self.flt_w * self.flt_w_d).clamp(0.0, 0.1);
+            if p.lpf_freq != 1.0 {
+                self.flt_dp += (sample - self.flt_p) * self.flt_w;
+                self.flt_dp -= self.flt_dp * self.flt_dmp;
+            } else {
+                self.flt_p = sample;
+                self.flt_dp = 0.0;
+            }
+            self.flt_p += self.flt_dp;
+
+            // High pass
+            self.flt_php += self.flt_p - previous;
+            self.flt_php -= self.flt_php * self.flt_hp;
+            sample = self.flt_php;
+
+            // Phaser
+            self.phaser_buffer[self.phaser_pos] = sample;
+            sample += self.phaser_buffer[(self.phaser_pos + PHASER_SIZE - self.phaser_offset) % PHASER_SIZE];
+            self.phaser_pos = (self.phaser_pos + 1) % PHASER_SIZE;
+
+            super_sample += sample * self.env_vol;
+        }
+
+        let sample = super_sample / SUPERSAMPLES as f32 * MASTER_VOLUME * 2.0 * p.volume;
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [SfxPreset; 7] = [
+        SfxPreset::Coin,
+        SfxPreset::Laser,
+        SfxPreset::Explosion,
+        SfxPreset::Powerup,
+        SfxPreset::Hit,
+        SfxPreset::Jump,
+        SfxPreset::Blip,
+    ];
+
+    #[test]
+    fn preset_is_deterministic_per_seed() {
+        for preset in PRESETS {
+            for seed in [0, 1, 12345] {
+                assert_eq!(SfxParams::preset(preset, seed), SfxParams::preset(preset, seed), "{:?} {}", preset, seed);
+            }
+            let differs = (1..8).any(|seed| SfxParams::preset(preset, 0) != SfxParams::preset(preset, seed));
+            assert!(differs, "{:?} ignores its seed", preset);
+        }
+    }
+
+    #[test]
+    fn mutate_is_deterministic() {
+        let params = SfxParams::preset(SfxPreset::Laser, 5);
+        let mut a = params;
+        let mut b = params;
+        a.mutate(&mut Rng::new(9));
+        b.mutate(&mut Rng::new(9));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn synthesis() {
+        for preset in PRESETS {
+            let params = SfxParams::preset(preset, 3);
+            let samples = params.synthesize(&mut Rng::new(3));
+            assert!(!samples.is_empty() && samples.len() <= MAX_SAMPLES, "{:?}", preset);
+            assert!(samples.iter().all(|s| (-1.0..=1.0).contains(s)), "{:?} clips", preset);
+            assert!(samples.iter().any(|s| *s != 0.0), "{:?} is silent", preset);
+            assert_eq!(samples, params.synthesize(&mut Rng::new(3)));
+        }
+    }
+
+    #[test]
+    fn wav_bytes() {
+        let params = SfxParams::preset(SfxPreset::Coin, 1);
+        let wav = params.to_wav(1);
+        let samples = params.synthesize(&mut Rng::new(1));
+
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+        assert_eq!(wav, params.to_wav(1));
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use soloud::*;
+
+use crate::math::geo::V2;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Attenuation {
+    None,
+    // Volume falls off with min_distance / distance, scaled by the rolloff factor
+    InverseDistance,
+    // Volume falls off linearly from min_distance to max_distance
+    Linear,
+    Exponential,
+}
+
+impl From<Attenuation> for AttenuationModel {
+    fn from(attenuation: Attenuation) -> Self {
+        match attenuation {
+            Attenuation::None => AttenuationModel::NoAttenuation,
+            Attenuation::InverseDistance => AttenuationModel::InverseDistance,
+            Attenuation::Linear => AttenuationModel::LinearDistance,
+            Attenuation::Exponential => AttenuationModel::ExponentialDistance,
+        }
+    }
+}
+
+// How a positional sound is heard, distances are in world units (pixels)
+#[derive(Copy, Clone, Debug)]
+pub struct Emitter {
+    pub attenuation: Attenuation,
+    pub rolloff: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // 0.0 disables the doppler effect
+    pub doppler: f32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            attenuation: Attenuation::InverseDistance,
+            rolloff: 1.0,
+            min_distance: 32.0,
+            max_distance: 1024.0,
+            doppler: 0.0,
+        }
+    }
+}
+
+impl Emitter {
+    pub fn with_attenuation(mut self, attenuation: Attenuation, rolloff: f32) -> Self {
+        self.attenuation = attenuation;
+        self.rolloff = rolloff;
+        self
+    }
+
+    pub fn with_distance(mut self, min_distance: f32, max_distance: f32) -> Self {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn with_doppler(mut self, doppler: f32) -> Self {
+        self.doppler = doppler;
+        self
+    }
+
+    pub(crate) fn apply(&self, soloud: &mut Soloud, handle: Handle) {
+        soloud.set_3d_source_minmax_distance(handle, self.min_distance, self.max_distance);
+        soloud.set_3d_source_attenuation(handle, self.attenuation.into(), self.rolloff);
+        soloud.set_3d_source_doppler_factor(handle, self.doppler);
+    }
+}
+
+struct EmitterState {
+    handle: Handle,
+    position: V2,
+    last_position: V2,
+}
+
+// Positional voices are played through soloud's 3D audio on the z = 0 plane
+pub(crate) struct SpatialAudio {
+    listener: Option<V2>,
+    camera: V2,
+    last_listener: V2,
+    emitters: Vec<EmitterState>,
+    last_update: Duration,
+}
+
+impl SpatialAudio {
+    pub(crate) fn new (soloud: &mut Soloud) -> Self {
+        // Sound speed in pixels per second, assuming roughly 32 pixels to the meter
+        soloud.set_3d_sound_speed(343.0 * 32.0);
+
+        Self {
+            listener: None,
+            camera: V2::new(0.0, 0.0),
+            last_listener: V2::new(0.0, 0.0),
+            emitters: vec![],
+            last_update: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn listener_position(&self) -> V2 {
+        self.listener.unwrap_or(self.camera)
+    }
+
+    pub(crate) fn set_listener(&mut self, position: Option<V2>) {
+        self.listener = position;
+    }
+
+    pub(crate) fn set_camera(&mut self, position: V2) {
+        self.camera = position;
+    }
+
+    pub(crate) fn add_emitter(&mut self, handle: Handle, position: V2) {
+        self.emitters.push(EmitterState {
+            handle,
+            position,
+            last_position: position,
+        });
+    }
+
+    pub(crate) fn set_emitter_position(&mut self, handle: Handle, position: V2) {
+        if let Some(emitter) = self.emitters.iter_mut().find(|emitter| emitter.handle == handle) {
+            emitter.position = position;
+        }
+    }
+
+    // Pushes listener and emitter positions to soloud, velocities for doppler are derived from
+    // how far things moved since the last update. `now` comes from the audio clock.
+    pub(crate) fn update(&mut self, soloud: &mut Soloud, now: Duration) {
+        let dt = now.saturating_sub(self.last_update).as_secs_f32();
+        self.last_update = now;
+        let velocity = |delta: V2| if dt > 0.0 { delta / dt } else { V2::new(0.0, 0.0) };
+
+        self.emitters.retain(|emitter| soloud.is_valid_voice_handle(emitter.handle));
+
+        let listener = self.listener_position();
+        let listener_velocity = velocity(listener - self.last_listener);
+        self.last_listener = listener;
+
+        // Screen space y points down, so up is -y for the listener to keep left and right intact
+        soloud.set_3d_listener_params(
+            listener.x, listener.y, 0.0,
+            0.0, 0.0, 1.0,
+            0.0, -1.0, 0.0,
+        );
+        soloud.set_3d_listener_velocity(listener_velocity.x, listener_velocity.y, 0.0);
+
+        for emitter in self.emitters.iter_mut() {
+            let emitter_velocity = velocity(emitter.position - emitter.last_position);
+            emitter.last_position = emitter.position;
+            soloud.set_3d_source_params_ex(
+                emitter.handle,
+                emitter.position.x, emitter.position.y, 0.0,
+                emitter_velocity.x, emitter_velocity.y, 0.0,
+            );
+        }
+
+        soloud.update_3d_audio();
+    }
+}
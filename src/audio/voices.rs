@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use soloud::*;
+
+use crate::sys::resource_manager::SoundID;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StealMode {
+    // The oldest instance of the sound is stopped to make room
+    Oldest,
+    // The new instance is not played
+    Reject,
+}
+
+// Limits on how a single sound can be played at once
+#[derive(Copy, Clone, Debug)]
+pub struct VoiceLimit {
+    pub max_instances: Option<u32>,
+    // Plays within this window of the last accepted play are dropped
+    pub cooldown: Duration,
+    pub steal: StealMode,
+}
+
+impl Default for VoiceLimit {
+    fn default() -> Self {
+        Self {
+            max_instances: None,
+            cooldown: Duration::ZERO,
+            steal: StealMode::Oldest,
+        }
+    }
+}
+
+impl VoiceLimit {
+    pub fn with_max_instances(mut self, max_instances: u32) -> Self {
+        self.max_instances = Some(max_instances);
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn with_steal(mut self, steal: StealMode) -> Self {
+        self.steal = steal;
+        self
+    }
+}
+
+struct TrackedVoice {
+    handle: Handle,
+    sound: SoundID,
+    priority: i32,
+    started: Duration,
+}
+
+// Keeps track of the sound effect voices started through `AudioSubsystem` so per sound limits and
+// the global voice cap can be enforced. Music is not counted.
+pub(crate) struct VoiceLimiter {
+    limits: HashMap<SoundID, VoiceLimit>,
+    last_played: HashMap<SoundID, Duration>,
+    voices: Vec<TrackedVoice>,
+    pub(crate) max_voices: Option<u32>,
+}
+
+impl VoiceLimiter {
+    pub(crate) fn new () -> Self {
+        Self {
+            limits: HashMap::new(),
+            last_played: HashMap::new(),
+            voices: vec![],
+            max_voices: None,
+        }
+    }
+
+    pub(crate) fn limit(&self, sound: SoundID) -> Option<VoiceLimit> {
+        self.limits.get(&sound).copied()
+    }
+
+    pub(crate) fn set_limit(&mut self, sound: SoundID, limit: Option<VoiceLimit>) {
+        match limit {
+            Some(limit) => { self.limits.insert(sound, limit); }
+            None => {
+                self.limits.remove(&sound);
+                self.last_played.remove(&sound);
+            }
+        }
+    }
+
+    pub(crate) fn instance_count(&mut self, soloud: &Soloud, sound: SoundID) -> usize {
+        self.prune(soloud);
+        self.voices.iter().filter(|voice| voice.sound == sound).count()
+    }
+
+    pub(crate) fn voice_count(&mut self, soloud: &Soloud) -> usize {
+        self.prune(soloud);
+        self.voices.len()
+    }
+
+    fn prune(&mut self, soloud: &Soloud) {
+        self.voices.retain(|voice| soloud.is_valid_voice_handle(voice.handle));
+    }
+
+    fn steal(&mut self, soloud: &mut Soloud, index: usize) {
+        let voice = self.voices.remove(index);
+        soloud.stop(voice.handle);
+    }
+
+    // Decides whether a new instance may start, stopping whatever voices it replaces. A new
+    // instance only steals a voice of the same or lower priority when the global cap is hit,
+    // the lowest priority and then oldest voice goes first.
+    pub(crate) fn admit(&mut self, soloud: &mut Soloud, sound: SoundID, priority: i32, now: Duration) -> bool {
+        self.prune(soloud);
+
+        let mut victims = match self.victims(sound, priority, now) {
+            Some(victims) => victims,
+            None => return false,
+        };
+        // Stolen from the back so the remaining indices stay valid
+        victims.sort_unstable();
+        for i in victims.into_iter().rev() {
+            self.steal(soloud, i);
+        }
+
+        self.last_played.insert(sound, now);
+        true
+    }
+
+    // Voices to stop for a new instance to start, None if it can't start. Both limits are checked
+    // before anything is stopped so a rejected instance never costs a voice.
+    fn victims(&self, sound: SoundID, priority: i32, now: Duration) -> Option<Vec<usize>> {
+        let mut victims = vec![];
+
+        if let Some(limit) = self.limits.get(&sound) {
+            if let Some(last) = self.last_played.get(&sound) {
+                if !limit.cooldown.is_zero() && now.saturating_sub(*last) < limit.cooldown {
+                    return None;
+                }
+            }
+
+            if let Some(max_instances) = limit.max_instances {
+                let instances = self.voices.iter().filter(|voice| voice.sound == sound).count();
+                if instances >= max_instances as usize {
+                    if limit.steal == StealMode::Reject || max_instances == 0 {
+                        return None;
+                    }
+                    // Voices are kept in start order, so the first match is the oldest
+                    victims.push(self.voices.iter().position(|voice| voice.sound == sound).unwrap());
+                }
+            }
+        }
+
+        if let Some(max_voices) = self.max_voices {
+            if self.voices.len() - victims.len() >= max_voices as usize {
+                if max_voices == 0 {
+                    return None;
+                }
+                let victim = self.voices.iter()
+                    .enumerate()
+                    .filter(|(i, voice)| !victims.contains(i) && voice.priority <= priority)
+                    .min_by_key(|(_, voice)| (voice.priority, voice.started))
+                    .map(|(i, _)| i)?;
+                victims.push(victim);
+            }
+        }
+
+        Some(victims)
+    }
+
+    pub(crate) fn track(&mut self, handle: Handle, sound: SoundID, priority: i32, now: Duration) {
+        self.voices.push(TrackedVoice {
+            handle,
+            sound,
+            priority,
+            started: now,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: SoundID = SoundID::from_raw_parts(0, 0);
+    const B: SoundID = SoundID::from_raw_parts(1, 0);
+
+    fn play(limiter: &mut VoiceLimiter, sound: SoundID, priority: i32, started_ms: u64) {
+        let handle = unsafe { Handle::from_raw(limiter.voices.len() as u32 + 1) };
+        limiter.track(handle, sound, priority, Duration::from_millis(started_ms));
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut limiter = VoiceLimiter::new();
+        play(&mut limiter, A, 0, 0);
+        assert_eq!(limiter.victims(A, 0, Duration::ZERO), Some(vec![]));
+    }
+
+    #[test]
+    fn per_sound_steals_oldest() {
+        let mut limiter = VoiceLimiter::new();
+        limiter.set_limit(A, Some(VoiceLimit::default().with_max_instances(2)));
+        play(&mut limiter, B, 0, 0);
+        play(&mut limiter, A, 0, 10);
+        play(&mut limiter, A, 0, 20);
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(30)), Some(vec![1]));
+        assert_eq!(limiter.victims(B, 0, Duration::from_millis(30)), Some(vec![]));
+    }
+
+    #[test]
+    fn per_sound_reject() {
+        let mut limiter = VoiceLimiter::new();
+        limiter.set_limit(A, Some(VoiceLimit::default().with_max_instances(1).with_steal(StealMode::Reject)));
+        play(&mut limiter, A, 0, 0);
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(10)), None);
+
+        limiter.set_limit(A, Some(VoiceLimit::default().with_max_instances(0)));
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(10)), None);
+    }
+
+    #[test]
+    fn cooldown() {
+        let mut limiter = VoiceLimiter::new();
+        limiter.set_limit(A, Some(VoiceLimit::default().with_cooldown(Duration::from_millis(50))));
+        limiter.last_played.insert(A, Duration::from_millis(100));
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(120)), None);
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(150)), Some(vec![]));
+        assert_eq!(limiter.victims(B, 0, Duration::from_millis(120)), Some(vec![]));
+    }
+
+    #[test]
+    fn global_steals_lowest_priority_then_oldest() {
+        let mut limiter = VoiceLimiter::new();
+        limiter.max_voices = Some(3);
+        play(&mut limiter, A, 5, 0);
+        play(&mut limiter, A, 1, 10);
+        play(&mut limiter, B, 1, 5);
+        assert_eq!(limiter.victims(B, 1, Duration::from_millis(20)), Some(vec![2]));
+        // Only voices of the same or lower priority can be stolen
+        assert_eq!(limiter.victims(B, 0, Duration::from_millis(20)), None);
+    }
+
+    #[test]
+    fn global_zero_rejects() {
+        let mut limiter = VoiceLimiter::new();
+        limiter.max_voices = Some(0);
+        assert_eq!(limiter.victims(A, 100, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn per_sound_steal_makes_room_globally() {
+        let mut limiter = VoiceLimiter::new();
+        limiter.max_voices = Some(2);
+        limiter.set_limit(A, Some(VoiceLimit::default().with_max_instances(1)));
+        play(&mut limiter, A, 0, 0);
+        play(&mut limiter, B, 5, 10);
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(20)), Some(vec![0]));
+    }
+
+    #[test]
+    fn rejected_by_global_cap_steals_nothing() {
+        // The per sound limit would steal the old A, but the global cap still has no room for a
+        // low priority voice, so nothing may be stopped
+        let mut limiter = VoiceLimiter::new();
+        limiter.set_limit(A, Some(VoiceLimit::default().with_max_instances(1)));
+        play(&mut limiter, A, 0, 0);
+        play(&mut limiter, B, 5, 10);
+        play(&mut limiter, B, 5, 20);
+        limiter.max_voices = Some(2);
+        assert_eq!(limiter.victims(A, 0, Duration::from_millis(30)), None);
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use luna::sys::pak::{PakArchive, PakWriter};
+
+const USAGE: &str = "usage:
+    lunapak build <directory> <output.pak> [prefix]
+    lunapak list <archive.pak>";
+
+fn build(dir: &Path, output: &Path, prefix: &str) -> std::io::Result<()> {
+    let mut writer = PakWriter::new();
+    writer.add_directory(dir, prefix)?;
+    writer.write_to_file(output)?;
+    println!("Packed {} files from {} into {}", writer.len(), dir.display(), output.display());
+    Ok(())
+}
+
+fn list(pak: &Path) -> std::io::Result<()> {
+    let archive = PakArchive::open(pak)?;
+    let mut files: Vec<&str> = archive.files().collect();
+    files.sort();
+    for file in files {
+        println!("{}", file);
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>().as_slice() {
+        ["build", dir, output] => build(Path::new(dir), Path::new(output), ""),
+        ["build", dir, output, prefix] => build(Path::new(dir), Path::new(output), prefix),
+        ["list", pak] => list(Path::new(pak)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("lunapak: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
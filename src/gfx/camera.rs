@@ -61,6 +61,26 @@ pub struct PerspectiveCamera {
     pub zfar: f32,
 }
 
+impl PerspectiveCamera {
+    pub fn new (width: u32, height: u32) -> Self {
+        Self {
+            pos: (0.0, 1.0, 2.0).into(),
+            dir: (0.0, 0.0, -1.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: width as f32 / height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+
+    pub fn resize (&mut self, width: u32, height: u32) {
+        if height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+}
+
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -0,0 +1,289 @@
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use crate::gfx::renderer2d::Renderer2D;
+use crate::math::geo::{V2, V4};
+
+const CIRCLE_SEGMENTS: usize = 24;
+const ARROW_HEAD_ANGLE: f32 = PI / 6.0;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DebugSpace {
+    World,
+    Screen,
+}
+
+#[derive(Clone, Debug)]
+pub enum DebugPrimitive {
+    Line { start: V2, end: V2 },
+    Rect { pos: V2, size: V2 },
+    Circle { center: V2, radius: f32 },
+    Arrow { start: V2, end: V2, head_size: f32 },
+    Text { pos: V2, text: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct DebugShape {
+    pub primitive: DebugPrimitive,
+    pub color: V4,
+    pub thickness: f32,
+    pub space: DebugSpace,
+    pub lifetime: Option<f32>,
+}
+
+impl DebugShape {
+    fn new (primitive: DebugPrimitive, color: V4) -> Self {
+        Self {
+            primitive,
+            color,
+            thickness: 1.0,
+            space: DebugSpace::World,
+            lifetime: None,
+        }
+    }
+
+    pub fn thickness(&mut self, thickness: f32) -> &mut Self {
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn screen_space(&mut self) -> &mut Self {
+        self.space = DebugSpace::Screen;
+        self
+    }
+
+    pub fn world_space(&mut self) -> &mut Self {
+        self.space = DebugSpace::World;
+        self
+    }
+
+    // Keep the shape around for the given number of seconds instead of a single frame
+    pub fn lifetime(&mut self, seconds: f32) -> &mut Self {
+        self.lifetime = Some(seconds);
+        self
+    }
+}
+
+pub struct DebugDraw {
+    pub enabled: bool,
+    shapes: Vec<DebugShape>,
+    // Shapes queued while disabled are written here and dropped
+    scratch: DebugShape,
+    last_render: Instant,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugDraw {
+    pub fn new () -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            shapes: Vec::new(),
+            scratch: DebugShape::new(DebugPrimitive::Line { start: V2::new(0.0, 0.0), end: V2::new(0.0, 0.0) }, V4::new(0.0, 0.0, 0.0, 0.0)),
+            last_render: Instant::now(),
+        }
+    }
+
+    fn push(&mut self, primitive: DebugPrimitive, color: V4) -> &mut DebugShape {
+        let shape = DebugShape::new(primitive, color);
+        if !self.enabled {
+            self.scratch = shape;
+            return &mut self.scratch;
+        }
+
+        self.shapes.push(shape);
+        self.shapes.last_mut().unwrap()
+    }
+
+    pub fn line(&mut self, start: V2, end: V2, color: V4) -> &mut DebugShape {
+        self.push(DebugPrimitive::Line { start, end }, color)
+    }
+
+    pub fn rect(&mut self, pos: V2, size: V2, color: V4) -> &mut DebugShape {
+        self.push(DebugPrimitive::Rect { pos, size }, color)
+    }
+
+    pub fn circle(&mut self, center: V2, radius: f32, color: V4) -> &mut DebugShape {
+        self.push(DebugPrimitive::Circle { center, radius }, color)
+    }
+
+    pub fn arrow(&mut self, start: V2, end: V2, color: V4) -> &mut DebugShape {
+        self.push(DebugPrimitive::Arrow { start, end, head_size: 8.0 }, color)
+    }
+
+    // Text is drawn with a built in 3x5 pixel font, `thickness` is the size of a font pixel
+    pub fn text(&mut self, pos: V2, text: &str, color: V4) -> &mut DebugShape {
+        self.push(DebugPrimitive::Text { pos, text: text.to_string() }, color)
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    // Draws every queued shape on top of whatever has been submitted to the renderer this frame
+    // and drops the shapes whose lifetime has run out.
+    pub fn render(&mut self, r2d: &mut Renderer2D) {
+        let now = Instant::now();
+        let dt = (now - self.last_render).as_secs_f32();
+        self.last_render = now;
+
+        if !self.enabled {
+            self.shapes.clear();
+            return;
+        }
+
+        let camera_offset = V2::new(r2d.camera.pos.x, r2d.camera.pos.y);
+        for shape in self.shapes.iter() {
+            let offset = match shape.space {
+                DebugSpace::World => V2::new(0.0, 0.0),
+                DebugSpace::Screen => camera_offset,
+            };
+            DebugDraw::draw_shape(r2d, shape, offset);
+        }
+
+        self.shapes.retain_mut(|shape| {
+            match &mut shape.lifetime {
+                Some(lifetime) => {
+                    *lifetime -= dt;
+                    *lifetime > 0.0
+                }
+                None => false,
+            }
+        });
+    }
+
+    fn draw_shape(r2d: &mut Renderer2D, shape: &DebugShape, offset: V2) {
+        let color = &shape.color;
+        let thickness = shape.thickness;
+
+        match &shape.primitive {
+            DebugPrimitive::Line { start, end } => {
+                r2d.draw_line(&(start + offset), &(end + offset), thickness, color);
+            }
+
+            DebugPrimitive::Rect { pos, size } => {
+                let tl = pos + offset;
+                let tr = tl + V2::new(size.x, 0.0);
+                let br = tl + size;
+                let bl = tl + V2::new(0.0, size.y);
+
+                r2d.draw_line(&tl, &tr, thickness, color);
+                r2d.draw_line(&tr, &br, thickness, color);
+                r2d.draw_line(&br, &bl, thickness, color);
+                r2d.draw_line(&bl, &tl, thickness, color);
+            }
+
+            DebugPrimitive::Circle { center, radius } => {
+                let center = center + offset;
+                let step = 2.0 * PI / CIRCLE_SEGMENTS as f32;
+                for i in 0..CIRCLE_SEGMENTS {
+                    let a0 = step * i as f32;
+                    let a1 = step * (i + 1) as f32;
+                    let p0 = center + V2::new(a0.cos(), a0.sin()) * *radius;
+                    let p1 = center + V2::new(a1.cos(), a1.sin()) * *radius;
+                    r2d.draw_line(&p0, &p1, thickness, color);
+                }
+            }
+
+            DebugPrimitive::Arrow { start, end, head_size } => {
+                let start = start + offset;
+                let end = end + offset;
+                r2d.draw_line(&start, &end, thickness, color);
+
+                let delta = start - end;
+                let angle = delta.y.atan2(delta.x);
+                for side in [-ARROW_HEAD_ANGLE, ARROW_HEAD_ANGLE] {
+                    let head = end + V2::new((angle + side).cos(), (angle + side).sin()) * *head_size;
+                    r2d.draw_line(&end, &head, thickness, color);
+                }
+            }
+
+            DebugPrimitive::Text { pos, text } => {
+                let pixel = V2::new(thickness, thickness);
+                let mut cursor = pos + offset;
+                for c in text.chars() {
+                    if c == '\n' {
+                        cursor.x = pos.x + offset.x;
+                        cursor.y += (GLYPH_HEIGHT + 1) as f32 * thickness;
+                        continue;
+                    }
+
+                    let glyph = glyph_bits(c);
+                    for row in 0..GLYPH_HEIGHT {
+                        for col in 0..GLYPH_WIDTH {
+                            let bit = (GLYPH_HEIGHT - 1 - row) * GLYPH_WIDTH + (GLYPH_WIDTH - 1 - col);
+                            if glyph & (1 << bit) != 0 {
+                                let p = cursor + V2::new(col as f32 * thickness, row as f32 * thickness);
+                                r2d.draw_quad_color(&p, &pixel, &V2::new(0.0, 0.0), None, color);
+                            }
+                        }
+                    }
+
+                    cursor.x += (GLYPH_WIDTH + 1) as f32 * thickness;
+                }
+            }
+        }
+    }
+}
+
+// 3x5 glyphs, one row per 3 bits with the top row in the highest bits
+#[rustfmt::skip]
+fn glyph_bits(c: char) -> u16 {
+    match c.to_ascii_uppercase() {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_011_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_010_010_010,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        'A' => 0b010_101_111_101_101,
+        'B' => 0b110_101_110_101_110,
+        'C' => 0b011_100_100_100_011,
+        'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_110_100_111,
+        'F' => 0b111_100_110_100_100,
+        'G' => 0b011_100_101_101_011,
+        'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111,
+        'J' => 0b001_001_001_101_010,
+        'K' => 0b101_101_110_101_101,
+        'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101,
+        'N' => 0b110_101_101_101_101,
+        'O' => 0b010_101_101_101_010,
+        'P' => 0b110_101_110_100_100,
+        'Q' => 0b010_101_101_110_011,
+        'R' => 0b110_101_110_101_101,
+        'S' => 0b011_100_010_001_110,
+        'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111,
+        'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101,
+        'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010,
+        'Z' => 0b111_001_010_100_111,
+        '.' => 0b000_000_000_000_010,
+        ',' => 0b000_000_000_010_100,
+        ':' => 0b000_010_000_010_000,
+        '-' => 0b000_000_111_000_000,
+        '+' => 0b000_010_111_010_000,
+        '/' => 0b001_001_010_100_100,
+        '(' => 0b001_010_010_010_001,
+        ')' => 0b100_010_010_010_100,
+        '=' => 0b000_111_000_111_000,
+        '!' => 0b010_010_010_000_010,
+        '?' => 0b110_001_010_000_010,
+        ' ' => 0,
+        _ => 0b111_111_111_111_111,
+    }
+}
@@ -1,4 +1,8 @@
 use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::sys::handle::ResourceId;
+use crate::sys::resource_manager::MeshID;
 
 pub trait LunarVertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -10,6 +14,7 @@ pub struct Vertex2D {
     pub pos: [f32; 2],
     pub tex_coords: [f32; 2],
     pub tex_idx: i32,
+    pub color: [f32; 4],
 }
 
 impl LunarVertex for Vertex2D {
@@ -34,6 +39,11 @@ impl LunarVertex for Vertex2D {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Sint32,
                 },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 4]>() + mem::size_of::<i32>()) as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ]
         }
     }
@@ -70,11 +80,11 @@ impl ModelVertex {
 
 pub struct Mesh<'a> {
     pub vertices: &'a [ModelVertex],
-    pub indices: &'a [u16],
+    pub indices: &'a [u32],
 }
 
 impl<'a> Mesh<'a> {
-    pub fn new (vertices: &'a[ModelVertex], indices: &'a[u16]) -> Self {
+    pub fn new (vertices: &'a[ModelVertex], indices: &'a[u32]) -> Self {
         Mesh {
             vertices,
             indices,
@@ -82,4 +92,77 @@ impl<'a> Mesh<'a> {
     }
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ModelInstance {
+    pub model: [[f32; 4]; 4],
+    // Multiplied with the texture, the material's base color
+    pub color: [f32; 4],
+}
 
+impl ModelInstance {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<ModelInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ]
+        }
+    }
+}
+
+pub struct GpuMesh {
+    pub id: MeshID,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+}
+
+impl GpuMesh {
+    pub fn from_mesh (device: &wgpu::Device, mesh: &Mesh, label: Option<&str>) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label,
+            contents: bytemuck::cast_slice(mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label,
+            contents: bytemuck::cast_slice(mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            id: ResourceId::invalid(),
+            vertex_buffer,
+            index_buffer,
+            num_indices: mesh.indices.len() as u32,
+        }
+    }
+}
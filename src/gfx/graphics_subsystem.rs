@@ -1,11 +1,24 @@
+use winit::dpi::PhysicalSize;
+
+use crate::gfx::util;
 use crate::window::window_subsystem::WindowSubsystem;
 
+pub struct Frame {
+    // None when rendering headless into the offscreen target
+    pub surface_texture: Option<wgpu::SurfaceTexture>,
+    pub view: wgpu::TextureView,
+    pub cleared: bool,
+}
+
 pub struct GraphicsSubsystem {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface,
+    pub surface: Option<wgpu::Surface>,
+    pub offscreen: Option<wgpu::Texture>,
     pub surface_format: wgpu::TextureFormat,
     pub config: wgpu::SurfaceConfiguration,
+    pub clear_color: wgpu::Color,
+    pub frame: Option<Frame>,
 }
 
 impl GraphicsSubsystem {
@@ -51,9 +64,156 @@ impl GraphicsSubsystem {
         Self {
             device,
             queue,
-            surface,
+            surface: Some(surface),
+            offscreen: None,
+            surface_format,
+            config,
+            clear_color: wgpu::Color {
+                r: 0.02,
+                g: 0.02,
+                b: 0.04,
+                a: 1.0,
+            },
+            frame: None,
+        }
+    }
+
+    // Renders into an offscreen texture instead of a window, returns None if there's no adapter
+    // with the features the renderers need
+    pub async fn headless (width: u32, height: u32) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            },
+        ).await?;
+
+        let desired_features =
+            wgpu::Features::TEXTURE_BINDING_ARRAY |
+                wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING;
+        if !adapter.features().contains(desired_features) { return None; }
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: desired_features,
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        ).await.ok()?;
+
+        let surface_format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        };
+        let offscreen = GraphicsSubsystem::create_offscreen(&device, &config);
+
+        Some(Self {
+            device,
+            queue,
+            surface: None,
+            offscreen: Some(offscreen),
             surface_format,
             config,
+            clear_color: wgpu::Color {
+                r: 0.02,
+                g: 0.02,
+                b: 0.04,
+                a: 1.0,
+            },
+            frame: None,
+        })
+    }
+
+    fn create_offscreen(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("graphics_subsystem.offscreen"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        })
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width > 0 && size.height > 0 {
+            self.config.width = size.width;
+            self.config.height = size.height;
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if self.offscreen.is_some() {
+                self.offscreen = Some(GraphicsSubsystem::create_offscreen(&self.device, &self.config));
+            }
+        }
+    }
+
+    // Acquires the surface texture every renderer draws into this frame, does nothing if the
+    // frame has already begun
+    pub fn begin_frame(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if self.frame.is_none() {
+            let (surface_texture, view) = match (&self.surface, &self.offscreen) {
+                (Some(surface), _) => {
+                    let surface_texture = surface.get_current_texture()?;
+                    let view = surface_texture.texture.create_view(
+                        &wgpu::TextureViewDescriptor::default()
+                    );
+                    (Some(surface_texture), view)
+                }
+                (None, Some(offscreen)) => {
+                    (None, offscreen.create_view(&wgpu::TextureViewDescriptor::default()))
+                }
+                (None, None) => return Err(wgpu::SurfaceError::Lost),
+            };
+            self.frame = Some(Frame {
+                surface_texture,
+                view,
+                cleared: false,
+            });
+        }
+        Ok(())
+    }
+
+    // The first pass drawing into a frame clears it, every pass after that draws on top
+    pub fn frame_load_op(&mut self) -> wgpu::LoadOp<wgpu::Color> {
+        let clear_color = self.clear_color;
+        match &mut self.frame {
+            Some(frame) if !frame.cleared => {
+                frame.cleared = true;
+                wgpu::LoadOp::Clear(clear_color)
+            }
+            _ => wgpu::LoadOp::Load,
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        if let Some(frame) = &self.frame {
+            if !frame.cleared {
+                let mut encoder = self.device.create_command_encoder(
+                    &wgpu::CommandEncoderDescriptor {
+                        label: Some("graphics_subsystem.clear_encoder")
+                    },
+                );
+                util::make_render_pass(&mut encoder, &frame.view, wgpu::LoadOp::Clear(self.clear_color));
+                self.queue.submit(std::iter::once(encoder.finish()));
+            }
+        }
+
+        if let Some(surface_texture) = self.frame.take().and_then(|frame| frame.surface_texture) {
+            surface_texture.present();
         }
     }
 }
\ No newline at end of file
@@ -1,7 +1,10 @@
-pub mod renderer;
 pub mod texture;
+pub mod shader;
 pub mod camera;
 pub mod geometry;
+pub mod model;
 pub mod renderer2d;
+pub mod renderer3d;
 pub mod graphics_subsystem;
-pub mod util;
\ No newline at end of file
+pub mod util;
+pub mod debug_draw;
\ No newline at end of file
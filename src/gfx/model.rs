@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use cgmath::{Quaternion, Vector3};
+
+use crate::gfx::geometry::{Mesh, ModelVertex};
+use crate::gfx::texture::TextureOptions;
+use crate::math::geo::V4;
+use crate::scene::node::{MeshRenderer, Node, RenderComponent};
+use crate::sys::resource_manager::{MeshHandle, ResourceError, ResourceManager, TextureHandle, TextureID, WHITE_TEXTURE_ID};
+use crate::sys::vfs;
+
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color: V4,
+    pub base_color_texture: Option<TextureHandle>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color: V4::new(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelMesh {
+    pub mesh: MeshHandle,
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelNode {
+    pub name: Option<String>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    // Indices into `Model::meshes`
+    pub meshes: Vec<usize>,
+    // Indices into `Model::nodes`
+    pub children: Vec<usize>,
+}
+
+impl Default for ModelNode {
+    fn default() -> Self {
+        Self {
+            name: None,
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            meshes: vec![],
+            children: vec![],
+        }
+    }
+}
+
+// Owns handles to every mesh and texture it uses, nodes created with `instantiate` only store ids
+// so the model has to be kept alive for as long as they are drawn
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    pub materials: Vec<Material>,
+    pub nodes: Vec<ModelNode>,
+    pub roots: Vec<usize>,
+}
+
+impl Model {
+    pub fn material_texture(&self, mesh: &ModelMesh) -> TextureID {
+        mesh.material
+            .and_then(|material| self.materials[material].base_color_texture.as_ref())
+            .map_or(WHITE_TEXTURE_ID, |texture| texture.id())
+    }
+
+    // Tints the texture, untextured materials are drawn in this color
+    pub fn material_color(&self, mesh: &ModelMesh) -> V4 {
+        mesh.material.map_or(V4::new(1.0, 1.0, 1.0, 1.0), |material| self.materials[material].base_color)
+    }
+
+    // Builds a scene graph node for the model, with one child per root node of the model
+    pub fn instantiate(&self, label: Option<&str>) -> Node<'static> {
+        let mut root = Node::new(label);
+        for &node in self.roots.iter() {
+            root.add_child(self.instantiate_node(node));
+        }
+        root
+    }
+
+    fn instantiate_node(&self, index: usize) -> Node<'static> {
+        let model_node = &self.nodes[index];
+        let mut node = Node::new(model_node.name.as_deref());
+
+        let transform = node.get_transform();
+        transform.set_position(model_node.translation.x, model_node.translation.y, model_node.translation.z);
+        transform.set_scale(model_node.scale.x, model_node.scale.y, model_node.scale.z);
+        transform.set_rotation_quaternion(model_node.rotation);
+
+        if !model_node.meshes.is_empty() {
+            let meshes = model_node.meshes.iter()
+                .map(|&mesh| {
+                    let mesh = &self.meshes[mesh];
+                    MeshRenderer {
+                        mesh: mesh.mesh.id(),
+                        texture: self.material_texture(mesh),
+                        color: self.material_color(mesh),
+                    }
+                })
+                .collect();
+            node.set_render_component(RenderComponent { meshes });
+        }
+
+        for &child in model_node.children.iter() {
+            node.add_child(self.instantiate_node(child));
+        }
+
+        node
+    }
+}
+
+fn decode_error(path: &str, format: &str, details: impl ToString) -> ResourceError {
+    ResourceError::Decode {
+        path: path.to_string(),
+        format: format.to_string(),
+        details: details.to_string(),
+    }
+}
+
+pub fn load_obj(res: &mut ResourceManager, path: &str) -> Result<Model, ResourceError> {
+    let bytes = res.vfs().read(path)?;
+    let base_dir = vfs::parent(path);
+
+    // MTL files are resolved relative to the OBJ through the virtual filesystem as well
+    let mounts = res.vfs().clone();
+    let (obj_models, obj_materials) = tobj::load_obj_buf(
+        &mut bytes.as_slice(),
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+        |mtl_path| {
+            let mtl_path = vfs::join(base_dir, &mtl_path.to_string_lossy());
+            let mtl_bytes = mounts.read(&mtl_path).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+            tobj::load_mtl_buf(&mut mtl_bytes.as_slice())
+        },
+    ).map_err(|e| decode_error(path, "obj", e))?;
+
+    // A missing or broken MTL file should not prevent the geometry from loading
+    let obj_materials = obj_materials.unwrap_or_default();
+
+    let mut model = Model::default();
+
+    for obj_material in obj_materials.iter() {
+        let mut material = Material {
+            name: Some(obj_material.name.clone()),
+            base_color: V4::new(obj_material.diffuse[0], obj_material.diffuse[1], obj_material.diffuse[2], 1.0),
+            ..Default::default()
+        };
+
+        if !obj_material.diffuse_texture.is_empty() {
+            let texture_path = vfs::join(base_dir, &obj_material.diffuse_texture);
+            material.base_color_texture = Some(res.load_texture(
+                &texture_path,
+                Some(obj_material.diffuse_texture.as_str()),
+                Some(TextureOptions::smooth().with_address_mode(wgpu::AddressMode::Repeat)),
+            )?);
+        }
+
+        model.materials.push(material);
+    }
+
+    let mut root = ModelNode {
+        name: Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+
+    let material_count = model.materials.len();
+    for obj_model in obj_models.iter() {
+        let obj_mesh = &obj_model.mesh;
+
+        let vertices: Vec<ModelVertex> = (0..obj_mesh.positions.len() / 3)
+            .map(|i| {
+                // OBJ texture coordinates start at the bottom left
+                let tex_coords = if obj_mesh.texcoords.len() >= (i + 1) * 2 {
+                    [obj_mesh.texcoords[i * 2], 1.0 - obj_mesh.texcoords[i * 2 + 1]]
+                } else {
+                    [0.0, 0.0]
+                };
+
+                ModelVertex {
+                    position: [
+                        obj_mesh.positions[i * 3],
+                        obj_mesh.positions[i * 3 + 1],
+                        obj_mesh.positions[i * 3 + 2],
+                    ],
+                    tex_coords,
+                }
+            })
+            .collect();
+
+        let mesh = res.add_mesh(&Mesh::new(&vertices, &obj_mesh.indices), Some(obj_model.name.as_str()))?;
+
+        root.meshes.push(model.meshes.len());
+        model.meshes.push(ModelMesh {
+            mesh,
+            material: obj_mesh.material_id.filter(|&id| id < material_count),
+        });
+    }
+
+    model.nodes.push(root);
+    model.roots.push(0);
+
+    Ok(model)
+}
+
+pub fn load_gltf(res: &mut ResourceManager, path: &str) -> Result<Model, ResourceError> {
+    // External buffers and images can only be resolved for files on disk, glTF files inside
+    // archives have to be self contained (.glb or embedded data uris)
+    let (document, buffers, images) = match res.vfs().real_path(path) {
+        Some(real_path) => gltf::import(real_path),
+        None => gltf::import_slice(res.vfs().read(path)?),
+    }.map_err(|e| decode_error(path, "gltf", e))?;
+
+    let mut model = Model::default();
+
+    // Images may be shared between materials, only upload each one once
+    let mut image_textures: HashMap<usize, TextureHandle> = HashMap::new();
+    for gltf_material in document.materials() {
+        let pbr = gltf_material.pbr_metallic_roughness();
+        let mut material = Material {
+            name: gltf_material.name().map(|n| n.to_string()),
+            base_color: pbr.base_color_factor().into(),
+            ..Default::default()
+        };
+
+        if let Some(info) = pbr.base_color_texture() {
+            let image_index = info.texture().source().index();
+            material.base_color_texture = Some(match image_textures.get(&image_index) {
+                Some(texture) => texture.clone(),
+                None => {
+                    let img = gltf_image_to_dynamic(&images[image_index])
+                        .map_err(|e| decode_error(path, "gltf image", e))?;
+                    let texture = res.add_texture_from_image(
+                        &img,
+                        info.texture().source().name(),
+                        &TextureOptions::smooth().with_address_mode(wgpu::AddressMode::Repeat),
+                    )?;
+                    image_textures.insert(image_index, texture.clone());
+                    texture
+                }
+            });
+        }
+
+        model.materials.push(material);
+    }
+
+    // glTF meshes are made of primitives, each one becomes a luna mesh
+    let mut mesh_primitives: Vec<Vec<usize>> = vec![];
+    for gltf_mesh in document.meshes() {
+        let mut primitives = vec![];
+        for primitive in gltf_mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles { continue; }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.collect(),
+                None => continue,
+            };
+            let tex_coords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                Some(tex_coords) => tex_coords.into_f32().collect(),
+                None => vec![[0.0, 0.0]; positions.len()],
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let vertices: Vec<ModelVertex> = positions.iter()
+                .zip(tex_coords.iter())
+                .map(|(&position, &tex_coords)| ModelVertex { position, tex_coords })
+                .collect();
+
+            let mesh = res.add_mesh(&Mesh::new(&vertices, &indices), gltf_mesh.name())?;
+
+            primitives.push(model.meshes.len());
+            model.meshes.push(ModelMesh {
+                mesh,
+                material: primitive.material().index(),
+            });
+        }
+        mesh_primitives.push(primitives);
+    }
+
+    for gltf_node in document.nodes() {
+        let (translation, rotation, scale) = gltf_node.transform().decomposed();
+        model.nodes.push(ModelNode {
+            name: gltf_node.name().map(|n| n.to_string()),
+            translation: translation.into(),
+            // glTF stores quaternions as [x, y, z, w]
+            rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+            scale: scale.into(),
+            meshes: gltf_node.mesh()
+                .map(|mesh| mesh_primitives[mesh.index()].clone())
+                .unwrap_or_default(),
+            children: gltf_node.children().map(|child| child.index()).collect(),
+        });
+    }
+
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+    model.roots = match scene {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None => (0..model.nodes.len()).collect(),
+    };
+
+    Ok(model)
+}
+
+fn gltf_image_to_dynamic(data: &gltf::image::Data) -> Result<image::DynamicImage, String> {
+    use gltf::image::Format;
+
+    let pixel_count = (data.width * data.height) as usize;
+    let rgba: Vec<u8> = match data.format {
+        Format::R8G8B8A8 => data.pixels.clone(),
+        Format::R8G8B8 => data.pixels.chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8 => data.pixels.chunks_exact(2)
+            .flat_map(|p| [p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8 => data.pixels.iter()
+            .flat_map(|&p| [p, p, p, 255])
+            .collect(),
+        format => return Err(format!("unsupported image format {:?}", format)),
+    };
+
+    if rgba.len() != pixel_count * 4 {
+        return Err(format!("{} bytes of pixel data, expected {}", rgba.len(), pixel_count * 4));
+    }
+
+    let buffer = image::RgbaImage::from_raw(data.width, data.height, rgba)
+        .ok_or_else(|| format!("could not create {}x{} image", data.width, data.height))?;
+
+    Ok(image::DynamicImage::ImageRgba8(buffer))
+}
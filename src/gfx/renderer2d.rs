@@ -12,8 +12,8 @@ use crate::gfx::graphics_subsystem::GraphicsSubsystem;
 use crate::gfx::texture::{Sprite, Texture};
 use crate::gfx::util;
 use crate::gfx::util::{pixel_to_tex_coords, Uniform};
-use crate::math::geo::{V2, v2_rotate_about_v2};
-use crate::sys::resource_manager::{ResourceManager, TextureID, WHITE_TEXTURE_ID};
+use crate::math::geo::{V2, V4, v2_rotate_about_v2};
+use crate::sys::resource_manager::{ResourceManager, ShaderID, TextureID, WHITE_TEXTURE_ID, with_gpu_error_scope};
 
 const MAX_QUADS: usize = 1024;
 const MAX_INDICES: usize = MAX_QUADS * 6;
@@ -152,6 +152,13 @@ impl Uniform for Uniforms {
     }
 }
 
+// A full batch waiting for `render`, 2D is only submitted after the 3D pass
+struct Batch {
+    vertices: Vec<Vertex2D>,
+    texture_slots: [TextureID; MAX_TEXTURES],
+    active_textures: usize,
+}
+
 pub struct Renderer2D {
     gfx: Rc<RefCell<GraphicsSubsystem>>,
     res: Rc<RefCell<ResourceManager>>,
@@ -167,9 +174,13 @@ pub struct Renderer2D {
     n_quads_drawn: usize,
 
     pipeline: wgpu::RenderPipeline,
+    shader: Option<ShaderID>,
+    shader_version: u32,
 
     texture_slots: [TextureID; MAX_TEXTURES],
     active_textures: usize,
+
+    batches: Vec<Batch>,
 }
 
 impl Renderer2D {
@@ -217,14 +228,7 @@ impl Renderer2D {
         let uniforms = Uniforms::new(&g.device, &[white_texture; MAX_TEXTURES]);
         std::mem::drop(r);
 
-        let pipeline = util::make_pipeline(
-            &g.device,
-            &[&uniforms.bind_group_layout()],
-            &[Vertex2D::desc()],
-            &shader_module,
-            &shader_module,
-            g.surface_format,
-        );
+        let pipeline = Renderer2D::build_pipeline(&g, &uniforms, &shader_module);
         std::mem::drop(g);
 
         Self {
@@ -234,21 +238,65 @@ impl Renderer2D {
             index_buffer: indices,
             vertex_buffer: vertices,
             pipeline,
+            shader: None,
+            shader_version: 0,
             uniforms,
             vertex_data: vertices_data,
             n_quads_drawn: 0,
             texture_slots: [WHITE_TEXTURE_ID; MAX_TEXTURES],
             active_textures: 1,
+            batches: Vec::new(),
         }
     }
 
-    pub fn resize(&mut self, size: PhysicalSize<u32>) {
-        let mut gfx = (*self.gfx).borrow_mut();
-        if size.width > 0 && size.height > 0 {
-            gfx.config.width = size.width;
-            gfx.config.height = size.height;
-            gfx.surface.configure(&gfx.device, &gfx.config);
+    fn build_pipeline(gfx: &GraphicsSubsystem, uniforms: &Uniforms, shader_module: &wgpu::ShaderModule) -> wgpu::RenderPipeline {
+        util::make_pipeline(
+            &gfx.device,
+            &[&uniforms.bind_group_layout()],
+            &[Vertex2D::desc()],
+            shader_module,
+            shader_module,
+            gfx.surface_format,
+            None,
+        )
+    }
+
+    // Draws with a shader loaded through the resource manager instead of the built in one, the
+    // pipeline is rebuilt automatically when the shader is hot reloaded
+    pub fn set_shader(&mut self, shader: Option<ShaderID>) {
+        self.shader = shader;
+
+        if shader.is_none() {
+            let gfx = (*self.gfx).borrow();
+            let shader_module = gfx.device.create_shader_module(include_wgsl!("shader2d.wgsl"));
+            self.pipeline = Renderer2D::build_pipeline(&gfx, &self.uniforms, &shader_module);
+            return;
         }
+
+        self.shader_version = u32::MAX;
+        self.refresh_pipeline();
+    }
+
+    fn refresh_pipeline(&mut self) {
+        let res = (*self.res).borrow();
+        let shader = match self.shader.and_then(|id| res.try_get_shader(id)) {
+            Some(shader) => shader,
+            None => return,
+        };
+        if shader.version == self.shader_version { return; }
+        self.shader_version = shader.version;
+
+        let gfx = (*self.gfx).borrow();
+        match with_gpu_error_scope(&gfx.device, "renderer2d.pipeline", || {
+            Renderer2D::build_pipeline(&gfx, &self.uniforms, &shader.module)
+        }) {
+            Ok(pipeline) => self.pipeline = pipeline,
+            Err(e) => log::warn!("Keeping previous pipeline: {}", e),
+        }
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        (*self.gfx).borrow_mut().resize(size);
     }
 
     pub fn render (&mut self) ->  Result<(), wgpu::SurfaceError> {
@@ -315,6 +363,44 @@ impl Renderer2D {
         );
     }
 
+    pub fn draw_quad_color (
+        &mut self,
+        pos: &V2,
+        size: &V2,
+        origin: &V2,
+        rotation: Option<f32>,
+        color: &V4,
+    ) {
+        let res = self.res.clone();
+        let res = (*res).borrow();
+        let texture = res.get_texture(WHITE_TEXTURE_ID);
+
+        self.draw_quad_texture_tinted(
+            pos,
+            size,
+            texture,
+            &V2::new(0.0, 0.0),
+            &V2::new(texture.size.width as f32, texture.size.height as f32),
+            origin,
+            rotation,
+            color,
+        );
+    }
+
+    pub fn draw_line (&mut self, start: &V2, end: &V2, thickness: f32, color: &V4) {
+        let delta = end - start;
+        let length = (delta.x * delta.x + delta.y * delta.y).sqrt();
+        if length <= f32::EPSILON { return; }
+
+        self.draw_quad_color(
+            start,
+            &V2::new(length, thickness),
+            &V2::new(0.0, 0.5),
+            Some(delta.y.atan2(delta.x)),
+            color,
+        );
+    }
+
     pub fn draw_quad_texture_ext (
         &mut self,
         pos: &V2,
@@ -325,17 +411,41 @@ impl Renderer2D {
         origin: &V2,
         rotation: Option<f32>,
     ) {
-        if self.n_quads_drawn == MAX_QUADS { self.flush().unwrap(); }
+        self.draw_quad_texture_tinted(
+            pos,
+            size,
+            texture,
+            src_pos,
+            src_size,
+            origin,
+            rotation,
+            &V4::new(1.0, 1.0, 1.0, 1.0),
+        );
+    }
+
+    pub fn draw_quad_texture_tinted (
+        &mut self,
+        pos: &V2,
+        size: &V2,
+        texture: &Texture,
+        src_pos: &V2,
+        src_size: &V2,
+        origin: &V2,
+        rotation: Option<f32>,
+        color: &V4,
+    ) {
+        if self.n_quads_drawn == MAX_QUADS { self.end_batch(); }
 
         let mut tex_idx: i32 = -1;
-        for i in 1..self.active_textures {
+        for i in 0..self.active_textures {
             if self.texture_slots[i] == texture.id {
-                tex_idx = texture.id as i32;
+                tex_idx = i as i32;
                 break;
             }
         }
 
         if tex_idx < 0 {
+            if self.active_textures == MAX_TEXTURES { self.end_batch(); }
             tex_idx = self.active_textures as i32;
             self.texture_slots[self.active_textures] = texture.id;
             self.active_textures += 1;
@@ -369,16 +479,50 @@ impl Renderer2D {
         let tt = tex_coords.y;
         let tb = tex_coords.y + tex_size.y;
 
+        let color: [f32; 4] = (*color).into();
         let offset = self.n_quads_drawn * 4;
-        self.vertex_data[0 + offset] = Vertex2D { pos: ltp.into(), tex_coords: [tl, tt], tex_idx };
-        self.vertex_data[1 + offset] = Vertex2D { pos: rtp.into(), tex_coords: [tr, tt], tex_idx };
-        self.vertex_data[2 + offset] = Vertex2D { pos: rbt.into(), tex_coords: [tr, tb], tex_idx };
-        self.vertex_data[3 + offset] = Vertex2D { pos: lbt.into(), tex_coords: [tl, tb], tex_idx };
+        self.vertex_data[0 + offset] = Vertex2D { pos: ltp.into(), tex_coords: [tl, tt], tex_idx, color };
+        self.vertex_data[1 + offset] = Vertex2D { pos: rtp.into(), tex_coords: [tr, tt], tex_idx, color };
+        self.vertex_data[2 + offset] = Vertex2D { pos: rbt.into(), tex_coords: [tr, tb], tex_idx, color };
+        self.vertex_data[3 + offset] = Vertex2D { pos: lbt.into(), tex_coords: [tl, tb], tex_idx, color };
         self.n_quads_drawn += 1;
     }
 
+    fn end_batch(&mut self) {
+        if self.n_quads_drawn == 0 { return; }
+
+        self.batches.push(Batch {
+            vertices: self.vertex_data[0..(self.n_quads_drawn * 4)].to_vec(),
+            texture_slots: self.texture_slots,
+            active_textures: self.active_textures,
+        });
+        self.n_quads_drawn = 0;
+        self.active_textures = 1;
+    }
+
+    // Submits every batch drawn since the last flush, in draw order
     pub fn flush (&mut self) -> Result<(), wgpu::SurfaceError> {
-        let gfx = (*self.gfx).borrow();
+        self.end_batch();
+        if self.batches.is_empty() { return Ok(()); }
+
+        self.refresh_pipeline();
+
+        let batches = std::mem::take(&mut self.batches);
+        for batch in batches.iter() {
+            self.submit_batch(batch)?;
+        }
+
+        Ok(())
+    }
+
+    fn submit_batch (&mut self, batch: &Batch) -> Result<(), wgpu::SurfaceError> {
+        let n_quads = batch.vertices.len() / 4;
+
+        let mut gfx = (*self.gfx).borrow_mut();
+        gfx.begin_frame()?;
+        let load = gfx.frame_load_op();
+        let gfx = &*gfx;
+        let frame = gfx.frame.as_ref().unwrap();
 
         self.uniforms.camera_uniform.update_view_proj(&mut self.camera);
         gfx.queue.write_buffer(
@@ -390,15 +534,9 @@ impl Renderer2D {
         gfx.queue.write_buffer(
             &self.vertex_buffer,
             0 as wgpu::BufferAddress,
-            bytemuck::cast_slice(
-                &self.vertex_data[0..(self.n_quads_drawn * 4)],
-            )
+            bytemuck::cast_slice(&batch.vertices),
         );
 
-        let surface_texture = gfx.surface.get_current_texture().unwrap();
-        let surface_view = surface_texture.texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
         let mut encoder = gfx.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor {
                 label: Some("renderer2d.command_encoder")
@@ -406,28 +544,19 @@ impl Renderer2D {
         );
 
         {
-            let mut rp = util::make_render_pass(
-                &mut encoder,
-                &surface_view,
-                wgpu::Color {
-                    r: 0.02,
-                    g: 0.02,
-                    b: 0.04,
-                    a: 1.0,
-                },
-            );
+            let mut rp = util::make_render_pass(&mut encoder, &frame.view, load);
 
             rp.set_pipeline(&self.pipeline);
             rp.set_vertex_buffer(
                 0,
                 self.vertex_buffer.slice(
-                    ..self.n_quads_drawn as u64 * 4 * std::mem::size_of::<Vertex2D>() as u64,
+                    ..n_quads as u64 * 4 * std::mem::size_of::<Vertex2D>() as u64,
                 ),
             );
 
             rp.set_index_buffer(
                 self.index_buffer.slice(
-                    ..self.n_quads_drawn as u64 * 6 * std::mem::size_of::<u16> as u64,
+                    ..n_quads as u64 * 6 * std::mem::size_of::<u16>() as u64,
                 ),
                 wgpu::IndexFormat::Uint16,
             );
@@ -435,20 +564,16 @@ impl Renderer2D {
             let res =(*self.res).borrow();
             let white_texture = res.get_texture(WHITE_TEXTURE_ID);
             let mut textures = [white_texture; MAX_TEXTURES];
-            for i in 0..self.active_textures { textures[i] = res.get_texture(self.texture_slots[i]) }
+            for i in 0..batch.active_textures { textures[i] = res.get_texture(batch.texture_slots[i]) }
             self.uniforms.update_textures(&gfx.device, &textures);
 
             rp.set_bind_group(0,self.uniforms.as_bind_group(), &[]);
 
-            rp.draw_indexed(0..(self.n_quads_drawn as u32 * 6), 0, 0..1);
+            rp.draw_indexed(0..(n_quads as u32 * 6), 0, 0..1);
         }
 
         gfx.queue.submit(std::iter::once(encoder.finish()));
 
-        surface_texture.present();
-
-        self.n_quads_drawn = 0;
-
         Ok(())
     }
-}
\ No newline at end of file
+}
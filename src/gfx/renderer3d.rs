@@ -0,0 +1,307 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cgmath::Matrix4;
+use wgpu::{BindGroup, BindGroupLayout, include_wgsl};
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::gfx::camera::{CameraUniform, PerspectiveCamera};
+use crate::gfx::geometry::{ModelInstance, ModelVertex};
+use crate::gfx::graphics_subsystem::GraphicsSubsystem;
+use crate::gfx::util;
+use crate::gfx::util::Uniform;
+use crate::math::geo::V4;
+use crate::scene::node::Transform;
+use crate::sys::resource_manager::{MeshID, ResourceManager, TextureID};
+
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+const INITIAL_INSTANCE_CAPACITY: usize = 256;
+
+pub struct CameraUniforms {
+    camera_buffer: wgpu::Buffer,
+    camera_uniform: CameraUniform,
+    bind_group: wgpu::BindGroup,
+    layout: wgpu::BindGroupLayout,
+}
+
+impl CameraUniforms {
+    pub fn new (device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("renderer3d.camera.bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ]
+            }
+        );
+
+        let camera_uniform = CameraUniform::new();
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("renderer3d.camera.buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("renderer3d.camera.bind_group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+            ]
+        });
+
+        Self {
+            camera_buffer,
+            camera_uniform,
+            bind_group,
+            layout,
+        }
+    }
+}
+
+impl Uniform for CameraUniforms {
+    fn as_bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+    fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+}
+
+struct DrawCommand {
+    mesh: MeshID,
+    texture: TextureID,
+}
+
+pub struct Renderer3D {
+    gfx: Rc<RefCell<GraphicsSubsystem>>,
+    res: Rc<RefCell<ResourceManager>>,
+
+    pub camera: PerspectiveCamera,
+
+    camera_uniforms: CameraUniforms,
+    texture_layout: wgpu::BindGroupLayout,
+
+    depth_view: wgpu::TextureView,
+
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+
+    draws: Vec<DrawCommand>,
+    instances: Vec<ModelInstance>,
+
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Renderer3D {
+    pub fn init(gfx: Rc<RefCell<GraphicsSubsystem>>, res: Rc<RefCell<ResourceManager>>) -> Self {
+        let g = (*gfx).borrow();
+
+        let texture_layout = g.device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("renderer3d.texture.bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ]
+            }
+        );
+
+        let camera_uniforms = CameraUniforms::new(&g.device);
+
+        let depth_view = Renderer3D::create_depth_view(&g.device, g.config.width, g.config.height);
+
+        let instance_buffer = Renderer3D::create_instance_buffer(&g.device, INITIAL_INSTANCE_CAPACITY);
+
+        let shader_module = g.device
+            .create_shader_module(include_wgsl!("shader.wgsl"));
+
+        let pipeline = util::make_pipeline(
+            &g.device,
+            &[&texture_layout, camera_uniforms.bind_group_layout()],
+            &[ModelVertex::desc(), ModelInstance::desc()],
+            &shader_module,
+            &shader_module,
+            g.surface_format,
+            Some(DEPTH_FORMAT),
+        );
+
+        let camera = PerspectiveCamera::new(g.config.width, g.config.height);
+        std::mem::drop(g);
+
+        Self {
+            gfx,
+            res,
+            camera,
+            camera_uniforms,
+            texture_layout,
+            depth_view,
+            instance_buffer,
+            instance_capacity: INITIAL_INSTANCE_CAPACITY,
+            draws: Vec::new(),
+            instances: Vec::new(),
+            pipeline,
+        }
+    }
+
+    fn create_depth_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("renderer3d.depth_texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("renderer3d.instance_buffer"),
+            size: (capacity * std::mem::size_of::<ModelInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width > 0 && size.height > 0 {
+            let gfx = (*self.gfx).borrow();
+            self.depth_view = Renderer3D::create_depth_view(&gfx.device, size.width, size.height);
+            self.camera.resize(size.width, size.height);
+        }
+    }
+
+    pub fn draw_mesh(&mut self, mesh: MeshID, texture: TextureID, transform: &Transform) {
+        self.draw_mesh_matrix(mesh, texture, &transform.get_local_transform());
+    }
+
+    pub fn draw_mesh_matrix(&mut self, mesh: MeshID, texture: TextureID, model: &Matrix4<f32>) {
+        self.draw_mesh_tinted(mesh, texture, model, &V4::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    pub fn draw_mesh_tinted(&mut self, mesh: MeshID, texture: TextureID, model: &Matrix4<f32>, color: &V4) {
+        self.draws.push(DrawCommand { mesh, texture });
+        self.instances.push(ModelInstance { model: (*model).into(), color: (*color).into() });
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if self.draws.is_empty() { return Ok(()); }
+
+        let mut gfx = (*self.gfx).borrow_mut();
+        gfx.begin_frame()?;
+        let load = gfx.frame_load_op();
+        let gfx = &*gfx;
+        let frame = gfx.frame.as_ref().unwrap();
+
+        self.camera_uniforms.camera_uniform.update_view_proj(&self.camera);
+        gfx.queue.write_buffer(
+            &self.camera_uniforms.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniforms.camera_uniform]),
+        );
+
+        if self.instances.len() > self.instance_capacity {
+            self.instance_capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = Renderer3D::create_instance_buffer(&gfx.device, self.instance_capacity);
+        }
+        gfx.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.instances));
+
+        let res = (*self.res).borrow();
+
+        // One bind group per texture used this frame
+        let mut texture_bind_groups: HashMap<TextureID, wgpu::BindGroup> = HashMap::new();
+        for draw in self.draws.iter() {
+            texture_bind_groups.entry(draw.texture).or_insert_with(|| {
+                let texture = res.get_texture(draw.texture);
+                gfx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("renderer3d.texture.bind_group"),
+                    layout: &self.texture_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&texture.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                        },
+                    ]
+                })
+            });
+        }
+
+        let mut encoder = gfx.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("renderer3d.command_encoder")
+            },
+        );
+
+        {
+            let mut rp = util::make_depth_render_pass(&mut encoder, &frame.view, &self.depth_view, load);
+
+            rp.set_pipeline(&self.pipeline);
+            rp.set_bind_group(1, self.camera_uniforms.as_bind_group(), &[]);
+            rp.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+            for (i, draw) in self.draws.iter().enumerate() {
+                let mesh = match res.try_get_mesh(draw.mesh) {
+                    Some(mesh) if mesh.num_indices > 0 => mesh,
+                    _ => continue,
+                };
+
+                rp.set_bind_group(0, &texture_bind_groups[&draw.texture], &[]);
+                rp.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                rp.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                rp.draw_indexed(0..mesh.num_indices, 0, i as u32..(i as u32 + 1));
+            }
+        }
+
+        gfx.queue.submit(std::iter::once(encoder.finish()));
+
+        self.draws.clear();
+        self.instances.clear();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use std::borrow::Cow;
+
+use crate::sys::handle::ResourceId;
+use crate::sys::resource_manager::ShaderID;
+
+// WGSL shader loaded at runtime. `version` is bumped every time the module is replaced so
+// renderers know when their pipelines need to be rebuilt.
+pub struct Shader {
+    pub id: ShaderID,
+    pub module: wgpu::ShaderModule,
+    pub version: u32,
+}
+
+impl Shader {
+    pub fn from_wgsl(device: &wgpu::Device, source: &str, label: Option<&str>) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(source.to_string())),
+        });
+
+        Self {
+            id: ResourceId::invalid(),
+            module,
+            version: 0,
+        }
+    }
+}
@@ -1,9 +1,14 @@
+use std::num::NonZeroU32;
+
 use anyhow::*;
 use image::GenericImageView;
+use wgpu::include_wgsl;
 use winit::dpi::PhysicalSize;
 
+use crate::gfx::util;
 use crate::math::geo::V2;
-use crate::sys::resource_manager::TextureID;
+use crate::sys::handle::ResourceId;
+use crate::sys::resource_manager::{TextureID, WHITE_TEXTURE_ID};
 
 #[derive(Copy, Clone)]
 pub struct Sprite {
@@ -15,7 +20,7 @@ pub struct Sprite {
 impl Default for Sprite {
     fn default() -> Self {
         Self {
-            texture_id: 0,
+            texture_id: WHITE_TEXTURE_ID,
             origin: V2::new(0.0, 0.0),
             scale: V2::new(1.0, 1.0),
         }
@@ -32,12 +37,114 @@ impl Sprite {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct TextureOptions {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub srgb: bool,
+    pub generate_mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            srgb: true,
+            generate_mipmaps: false,
+        }
+    }
+}
+
+impl TextureOptions {
+    // Nearest filtering everywhere so pixel art stays crisp
+    pub fn pixel_art() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+
+    // Trilinear filtering with a full mip chain, for textures that get scaled down
+    pub fn smooth() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            generate_mipmaps: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_filter(mut self, mag_filter: wgpu::FilterMode, min_filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn with_address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self
+    }
+
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn with_mipmaps(mut self, generate_mipmaps: bool) -> Self {
+        self.generate_mipmaps = generate_mipmaps;
+        self
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        if self.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+
+    pub fn mip_level_count(&self, width: u32, height: u32) -> u32 {
+        if self.generate_mipmaps {
+            32 - width.max(height).max(1).leading_zeros()
+        } else {
+            1
+        }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            ..Default::default()
+        })
+    }
+}
+
 pub struct Texture {
     pub id: TextureID,
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
     pub size: PhysicalSize<u32>,
+    pub format: wgpu::TextureFormat,
+    pub options: TextureOptions,
+    // Created with `new_dynamic`, only these can be resized
+    pub dynamic: bool,
 }
 
 impl Texture {
@@ -46,9 +153,10 @@ impl Texture {
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: Option<&str>,
+        options: &TextureOptions,
     ) -> Result<Self> {
        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, label)
+        Self::from_image(device, queue, &img, label, options)
     }
 
     pub fn from_image(
@@ -56,6 +164,7 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        options: &TextureOptions,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -66,14 +175,22 @@ impl Texture {
             depth_or_array_layers: 1,
         };
 
+        let format = options.format();
+        let mip_level_count = options.mip_level_count(dimensions.0, dimensions.1);
+
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+
         let texture = device.create_texture(
             &wgpu::TextureDescriptor {
                 size: texture_size,
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                format,
+                usage,
                 label,
             }
         );
@@ -94,17 +211,245 @@ impl Texture {
             texture_size,
         );
 
+        if mip_level_count > 1 {
+            generate_mipmaps(device, queue, &texture, format, mip_level_count);
+        }
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        let sampler = options.create_sampler(device);
+
+        Ok(Self {
+            id: ResourceId::invalid(),
+            texture,
+            view,
+            sampler,
+            size: PhysicalSize::new(dimensions.0, dimensions.1),
+            format,
+            options: *options,
+            dynamic: false,
+        })
+    }
+
+    // Creates an empty texture meant to be written to from the CPU every frame
+    pub fn new_dynamic(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Self {
+        let texture = Self::create_dynamic_texture(device, width, height, format, label);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = options.create_sampler(device);
+
+        Self {
+            id: ResourceId::invalid(),
+            texture,
+            view,
+            sampler,
+            size: PhysicalSize::new(width, height),
+            format,
+            options: TextureOptions {
+                generate_mipmaps: false,
+                ..*options
+            },
+            dynamic: true,
+        }
+    }
+
+    fn create_dynamic_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> wgpu::Texture {
+        device.create_texture(
+            &wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                label,
+            }
+        )
+    }
+
+    pub fn bytes_per_pixel(&self) -> u32 {
+        self.format.describe().block_size as u32
+    }
+
+    // Uploads tightly packed pixels into the rectangle at (x, y) of mip level 0
+    pub fn write_region(
+        &self,
+        queue: &wgpu::Queue,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<()> {
+        if x + width > self.size.width || y + height > self.size.height {
+            bail!(
+                "Region {}x{} at ({}, {}) is outside of the {}x{} texture",
+                width, height, x, y, self.size.width, self.size.height,
+            );
+        }
+
+        let bytes_per_row = width * self.bytes_per_pixel();
+        if pixels.len() < (bytes_per_row * height) as usize {
+            bail!("Expected {} bytes of pixel data, got {}", bytes_per_row * height, pixels.len());
+        }
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(bytes_per_row),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Recreates the GPU texture at the new size, keeping the overlapping pixels. The id, sampler
+    // and options are left untouched so sprites referencing this texture keep working.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) -> Result<()> {
+        if !self.dynamic {
+            bail!("Only dynamic textures can be resized");
+        }
+        if width == 0 || height == 0 {
+            bail!("Can't resize a texture to {}x{}", width, height);
+        }
+
+        let texture = Self::create_dynamic_texture(device, width, height, self.format, None);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("texture.resize_encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            self.texture.as_image_copy(),
+            texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: width.min(self.size.width),
+                height: height.min(self.size.height),
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.texture = texture;
+        self.size = PhysicalSize::new(width, height);
+
+        Ok(())
+    }
+}
+
+// Fills mip levels 1..mip_level_count by repeatedly blitting each level into the next one
+pub fn generate_mipmaps(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+) {
+    let shader = device.create_shader_module(include_wgsl!("mipmap.wgsl"));
+
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("texture.mipmap_pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        depth_stencil: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
             ..Default::default()
+        },
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("texture.mipmap_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    let views: Vec<wgpu::TextureView> = (0..mip_level_count)
+        .map(|mip| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("texture.mipmap_view"),
+                base_mip_level: mip,
+                mip_level_count: NonZeroU32::new(1),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("texture.mipmap_encoder"),
+    });
+
+    for target_mip in 1..mip_level_count as usize {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("texture.mipmap_bind_group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&views[target_mip - 1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
         });
 
-        Ok(Self { id: 0, texture, view, sampler, size: PhysicalSize::new(dimensions.0, dimensions.1) })
+        let mut rp = util::make_render_pass(
+            &mut encoder,
+            &views[target_mip],
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+        );
+        rp.set_pipeline(&pipeline);
+        rp.set_bind_group(0, &bind_group, &[]);
+        rp.draw(0..3, 0..1);
     }
-}
\ No newline at end of file
+
+    queue.submit(std::iter::once(encoder.finish()));
+}
@@ -13,6 +13,7 @@ pub fn make_pipeline (
     vertex_shader: &wgpu::ShaderModule,
     fragment_shader: &wgpu::ShaderModule,
     output_format: wgpu::TextureFormat,
+    depth_format: Option<wgpu::TextureFormat>,
 ) -> wgpu::RenderPipeline {
     let render_pipeline_layout =
         device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -38,7 +39,13 @@ pub fn make_pipeline (
                         write_mask: wgpu::ColorWrites::ALL,
                     })]
                 }),
-                depth_stencil: None,
+                depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                    format,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
                 primitive: wgpu::PrimitiveState {
                     topology: wgpu::PrimitiveTopology::TriangleList,
                     polygon_mode: wgpu::PolygonMode::Fill,
@@ -53,14 +60,14 @@ pub fn make_pipeline (
 pub fn make_render_pass<'a>(
     encoder: &'a mut wgpu::CommandEncoder,
     target: &'a wgpu::TextureView,
-    clear_color: wgpu::Color
+    load: wgpu::LoadOp<wgpu::Color>,
 ) -> wgpu::RenderPass<'a> {
    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
        label: Some("render_pass"),
        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
            view: target,
            ops: wgpu::Operations {
-               load: wgpu::LoadOp::Clear(clear_color),
+               load,
                store: true,
            },
            resolve_target: None,
@@ -69,6 +76,33 @@ pub fn make_render_pass<'a>(
    })
 }
 
+pub fn make_depth_render_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    target: &'a wgpu::TextureView,
+    depth: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPass<'a> {
+   encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+       label: Some("depth_render_pass"),
+       color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+           view: target,
+           ops: wgpu::Operations {
+               load,
+               store: true,
+           },
+           resolve_target: None,
+       })],
+       depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+           view: depth,
+           depth_ops: Some(wgpu::Operations {
+               load: wgpu::LoadOp::Clear(1.0),
+               store: true,
+           }),
+           stencil_ops: None,
+       }),
+   })
+}
+
 pub fn pixel_to_tex_coords(coords: &V2, texture: &Texture) -> V2 {
     V2::new(coords.x / texture.size.width as f32, coords.y / texture.size.height as f32)
 }
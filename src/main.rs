@@ -1,4 +1,5 @@
 use std::f32::consts::PI;
+use std::time::Duration;
 
 use winit::event::VirtualKeyCode;
 
@@ -6,6 +7,8 @@ use luna::audio::audio_subsystem::Sound;
 use luna::gfx::texture::Sprite;
 use luna::math::geo::V2;
 use luna::sys::app::{Context, LunarApp, run};
+use luna::sys::input_map::{AxisBinding, Binding, GamepadAxis, GamepadButton, InputMap};
+use luna::sys::manifest::AssetBundle;
 use luna::world::components::SpriteComponent;
 use luna::world::world::{Entity, EntityBuilder, EntityID, World};
 
@@ -14,6 +17,9 @@ pub struct TestApp {
     tree: Sprite,
     synth: Sound,
 
+    // Resources are freed once their last handle is dropped
+    assets: AssetBundle,
+
     world: World,
     player: Option<EntityID>,
 }
@@ -25,6 +31,7 @@ impl Default for TestApp {
             tilemap: Sprite::default(),
             tree: Sprite::default(),
             synth: Sound::default(),
+            assets: AssetBundle::default(),
             world: World::new(),
             player: None,
         }
@@ -35,19 +42,39 @@ impl LunarApp for TestApp {
     fn setup(&mut self, ctx: &mut Context) {
         let mut res = (*ctx.res).borrow_mut();
 
+        if cfg!(debug_assertions) {
+            res.enable_hot_reload(Duration::from_millis(500));
+        }
+
+        self.assets = res.load_manifest("res/assets.ron").unwrap();
+        std::mem::drop(res);
+
+        ctx.input.set_input_map(InputMap::new()
+            .with_action("play_synth", &[
+                Binding::Key(VirtualKeyCode::Space),
+                Binding::GamepadButton(GamepadButton::South),
+            ])
+            .with_axis("move_x", AxisBinding::keys(VirtualKeyCode::A, VirtualKeyCode::D)
+                .with_negative(Binding::Key(VirtualKeyCode::Left))
+                .with_positive(Binding::Key(VirtualKeyCode::Right))
+                .with_analog(GamepadAxis::LeftStickX)));
+
+        let tilesheet = self.assets.texture("tilesheet").unwrap();
         self.tilemap = Sprite::new(
-        res.load_texture("res/tilesheet.png", None).unwrap(),
+        tilesheet.id(),
         V2::new(0.5, 0.5),
         V2::new(1.0, 1.0),
         );
 
+        let happy_tree = self.assets.texture("happy-tree").unwrap();
         self.tree = Sprite::new(
-        res.load_texture("res/happy-tree.png", None).unwrap(),
+        happy_tree.id(),
         V2::new(0.5, 0.5),
         V2::new(1.0, 1.0),
         );
 
-        self.synth = Sound::new(res.load_sound("res/synth.wav").unwrap());
+        let synth = self.assets.sound("synth").unwrap();
+        self.synth = Sound::new(synth.id());
 
         let player = Entity::builder()
             .add_sprite_component(
@@ -75,18 +102,12 @@ impl LunarApp for TestApp {
 
         r2d.draw_sprite(&self.tree, &V2::new(300.0, 300.0));
 
-        if ctx.input.key_pressed(VirtualKeyCode::Space) {
+        if ctx.input.action_pressed("play_synth") {
             ctx.audio.play_sound(&self.synth);
         }
 
         if let Some(player) = self.world.get_entity_mut(self.player.unwrap()) {
-            if ctx.input.key_held(VirtualKeyCode::A) {
-                player.transform.position.x -= 1.0;
-            }
-
-            if ctx.input.key_held(VirtualKeyCode::D) {
-                player.transform.position.x += 1.0;
-            }
+            player.transform.position.x += ctx.input.axis_value("move_x");
         }
 
         self.world.update(ctx);
@@ -1 +1,2 @@
-pub mod geo;
\ No newline at end of file
+pub mod geo;
+pub mod rng;
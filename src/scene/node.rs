@@ -3,6 +3,10 @@ use std::fmt::{Debug, Formatter};
 
 use cgmath::*;
 
+use crate::gfx::renderer3d::Renderer3D;
+use crate::math::geo::V4;
+use crate::sys::resource_manager::{MeshID, TextureID};
+
 pub trait Component {
     fn start();
     fn update();
@@ -42,6 +46,10 @@ impl Transform {
         self.rotation = Euler::new(Deg(x), Deg(y), Deg(z)).into();
         self.update_local_transform();
     }
+    pub fn set_rotation_quaternion(&mut self, rotation: Quaternion<f32>) {
+        self.rotation = rotation;
+        self.update_local_transform();
+    }
 
     pub fn get_position(&self) -> Vector3<f32> {
         self.position
@@ -66,19 +74,32 @@ impl Transform {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct MeshRenderer {
+    pub mesh: MeshID,
+    pub texture: TextureID,
+    pub color: V4,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderComponent {
+    pub meshes: Vec<MeshRenderer>,
+}
+
 pub struct Node<'node> {
-    label: Option<&'static str>,
+    label: Option<String>,
     parent: Option<&'node Node<'node>>,
     children: Vec<Node<'node>>,
     active: bool,
 
     transform: Transform,
+    render_component: Option<RenderComponent>,
 }
 
 impl<'node> Debug for Node<'node> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let parent = if let Some(p) = self.parent {
-            p.label
+            p.label.as_deref()
         } else {
             Some("")
         };
@@ -89,7 +110,7 @@ impl<'node> Debug for Node<'node> {
             .field("transform", &self.transform)
             .field("parent", &parent)
             .field("child_count", &self.children.len())
-            //.field("render_component", &self.render_component.is_some())
+            .field("render_component", &self.render_component.is_some())
             //.field("light_component", &self.light_component.is_some())
             .finish()
     }
@@ -103,16 +124,16 @@ impl<'node> Default for Node<'node> {
             children: vec![],
             active: true,
             transform: Transform::default(),
-            //render_component: None,
+            render_component: None,
             //light_component: None,
         }
     }
 }
 
 impl<'node> Node<'node> {
-    pub fn new (label: Option<&'static str>) -> Self {
+    pub fn new (label: Option<&str>) -> Self {
         Self {
-            label,
+            label: label.map(|l| l.to_string()),
             ..Default::default()
         }
     }
@@ -120,15 +141,31 @@ impl<'node> Node<'node> {
        self.children.push(node);
     }
 
+    pub fn get_children(&self) -> &Vec<Node<'node>> {
+        &self.children
+    }
+
     pub fn get_transform (&mut self) -> &mut Transform {
        &mut self.transform
     }
 
+    pub fn get_render_component(&self) -> Option<&RenderComponent> {
+        self.render_component.as_ref()
+    }
+
+    pub fn set_render_component(&mut self, render_component: RenderComponent) {
+        self.render_component = Some(render_component);
+    }
+
     pub fn update(&mut self, _dt: f32) {
     }
 
-    pub fn set_label(&mut self, label: &'static str) {
-        self.label = Some(label);
+    pub fn get_label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn set_label(&mut self, label: &str) {
+        self.label = Some(label.to_string());
     }
 }
 
@@ -160,4 +197,23 @@ impl<'scene> Scene<'scene> {
             Scene::recursive_update(child, dt);
         }
     }
+
+    pub fn render (&self, r3d: &mut Renderer3D) {
+        Scene::recursive_render(&self.root, &Matrix4::identity(), r3d);
+    }
+
+    pub fn recursive_render(node: &Node, parent_transform: &Matrix4<f32>, r3d: &mut Renderer3D) {
+        if !node.active { return; }
+
+        let world_transform = parent_transform * node.transform.get_local_transform();
+        if let Some(render_component) = &node.render_component {
+            for renderer in render_component.meshes.iter() {
+                r3d.draw_mesh_tinted(renderer.mesh, renderer.texture, &world_transform, &renderer.color);
+            }
+        }
+
+        for child in node.children.iter() {
+            Scene::recursive_render(child, &world_transform, r3d);
+        }
+    }
 }
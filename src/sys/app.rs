@@ -7,17 +7,59 @@ use winit::{
 };
 
 use crate::audio::audio_subsystem::AudioSubsystem;
+use crate::audio::backend::AudioBackend;
+use crate::gfx::debug_draw::DebugDraw;
 use crate::gfx::graphics_subsystem::GraphicsSubsystem;
 use crate::gfx::renderer2d::*;
+use crate::gfx::renderer3d::Renderer3D;
+use crate::math::geo::V2;
 use crate::sys::input_subsystem::InputSubsystem;
 use crate::sys::resource_manager::ResourceManager;
 use crate::window::window_subsystem::{WindowConfig, WindowSubsystem};
 
 pub struct Context {
+    gfx: Rc<RefCell<GraphicsSubsystem>>,
     pub res: Rc<RefCell<ResourceManager>>,
     pub r2d: Renderer2D,
+    pub r3d: Renderer3D,
     pub input: InputSubsystem,
     pub audio: AudioSubsystem,
+    pub debug: DebugDraw,
+}
+
+impl Context {
+    // A context without a window, for driving `LunarApp::update` from tests. Renderers draw into
+    // an offscreen texture, audio uses the offline backend and gamepads are not polled, input is
+    // driven with `inject` and `flush`. Returns None if there's no usable GPU adapter.
+    pub async fn headless (width: u32, height: u32) -> Option<Self> {
+        let gfx = Rc::new(RefCell::new(GraphicsSubsystem::headless(width, height).await?));
+        let res = Rc::new(RefCell::new(ResourceManager::new(gfx.clone())));
+
+        let r2d = Renderer2D::init(gfx.clone(), res.clone());
+        let r3d = Renderer3D::init(gfx.clone(), res.clone());
+        let audio = AudioSubsystem::with_backend(res.clone(), AudioBackend::offline());
+
+        Some(Self {
+            gfx,
+            res,
+            r2d,
+            r3d,
+            input: InputSubsystem::new(),
+            audio,
+            debug: DebugDraw::new(),
+        })
+    }
+
+    // The frame is shared by every renderer, 3D is drawn first so 2D can be used for HUDs
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let frame = (*self.gfx).borrow_mut().begin_frame();
+        let result = frame
+            .and_then(|_| self.r3d.render())
+            .and_then(|_| self.r2d.render());
+        (*self.gfx).borrow_mut().end_frame();
+        (*self.res).borrow_mut().collect_garbage();
+        result
+    }
 }
 
 pub trait LunarApp {
@@ -30,7 +72,8 @@ pub async fn run<T: LunarApp + 'static>(mut client: T) {
     // Initialize subsystems
     env_logger::init();
     let event_loop = EventLoop::new();
-    let input = InputSubsystem::new();
+    let mut input = InputSubsystem::new();
+    input.enable_gamepads();
 
     let window_sys = WindowSubsystem::new(WindowConfig::default(), &event_loop);
 
@@ -43,14 +86,17 @@ pub async fn run<T: LunarApp + 'static>(mut client: T) {
 
     // Initialize subsystems that depend on loaded resources
     let r2d = Renderer2D::init(gfx.clone(), res.clone());
+    let r3d = Renderer3D::init(gfx.clone(), res.clone());
     let audio = AudioSubsystem::new(res.clone());
 
     let mut ctx = Context {
-        // TODO(bryson): Do we actually need gfx here?
+        gfx,
         res,
         r2d,
+        r3d,
         input,
         audio,
+        debug: DebugDraw::new(),
     };
 
     // Run app setup
@@ -66,15 +112,29 @@ pub async fn run<T: LunarApp + 'static>(mut client: T) {
 
            if let Some(new_size) = ctx.input.window_resized() {
                ctx.r2d.resize(new_size);
+               ctx.r3d.resize(new_size);
            }
 
            window_sys.window.request_redraw();
 
+           (*ctx.res).borrow_mut().update_loading();
+           (*ctx.res).borrow_mut().poll_hot_reload();
            client.update(&mut ctx);
-
-           match ctx.r2d.render() {
+           let camera = &ctx.r2d.camera;
+           ctx.audio.set_camera_position(V2::new(
+               camera.pos.x + camera.size.width as f32 / 2.0,
+               camera.pos.y + camera.size.height as f32 / 2.0,
+           ));
+           ctx.audio.update();
+           ctx.debug.render(&mut ctx.r2d);
+
+           match ctx.render() {
                Ok(_) => {}
-               Err(wgpu::SurfaceError::Lost) => ctx.r2d.resize(window_sys.window.inner_size()),
+               Err(wgpu::SurfaceError::Lost) => {
+                   let size = window_sys.window.inner_size();
+                   ctx.r2d.resize(size);
+                   ctx.r3d.resize(size);
+               }
                Err(wgpu::SurfaceError::OutOfMemory) => {
                    client.shutdown(&mut ctx);
                    control_flow.set_exit()
@@ -83,4 +143,69 @@ pub async fn run<T: LunarApp + 'static>(mut client: T) {
            }
        }
     });
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::geo::V4;
+    use crate::sys::input_map::{Binding, InputMap};
+
+    #[derive(Default)]
+    struct JumpApp {
+        jumps: u32,
+        frames: u32,
+    }
+
+    impl LunarApp for JumpApp {
+        fn setup(&mut self, ctx: &mut Context) {
+            ctx.input.set_input_map(InputMap::new()
+                .with_action("jump", &[Binding::Key(VirtualKeyCode::Space)]));
+        }
+
+        fn update(&mut self, ctx: &mut Context) {
+            self.frames += 1;
+            if ctx.input.action_pressed("jump") {
+                self.jumps += 1;
+            }
+            ctx.r2d.draw_quad_color(
+                &V2::new(0.0, 0.0),
+                &V2::new(8.0, 8.0),
+                &V2::new(0.0, 0.0),
+                None,
+                &V4::new(1.0, 1.0, 1.0, 1.0),
+            );
+        }
+
+        fn shutdown(&mut self, _ctx: &mut Context) {}
+    }
+
+    #[test]
+    fn update_with_injected_keys() {
+        let mut ctx = match pollster::block_on(Context::headless(64, 64)) {
+            Some(ctx) => ctx,
+            None => {
+                eprintln!("No GPU adapter available, skipping");
+                return;
+            }
+        };
+        let mut app = JumpApp::default();
+        app.setup(&mut ctx);
+
+        ctx.input.inject_key_down(VirtualKeyCode::Space);
+        app.update(&mut ctx);
+        ctx.input.flush();
+        app.update(&mut ctx);
+        ctx.input.inject_key_up(VirtualKeyCode::Space);
+        ctx.input.flush();
+        ctx.input.inject_key_tap(VirtualKeyCode::Space);
+        app.update(&mut ctx);
+
+        assert_eq!(app.frames, 3);
+        assert_eq!(app.jumps, 2);
+
+        ctx.render().unwrap();
+
+        app.shutdown(&mut ctx);
+    }
+}
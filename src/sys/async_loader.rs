@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::audio::audio_subsystem::DecodedSound;
+use crate::sys::handle::Handle;
+use crate::sys::resource_manager::{image_format_name, sound_format_name, ResourceError};
+use crate::sys::vfs::Vfs;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LoadStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+enum LoadState<T> {
+    Pending,
+    Ready(Handle<T>),
+    Failed(Rc<ResourceError>),
+}
+
+// Result of an asynchronous load, resolved on the main thread by `ResourceManager::update_loading`
+pub struct LoadingHandle<T> {
+    state: Rc<RefCell<LoadState<T>>>,
+}
+
+impl<T> Clone for LoadingHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> LoadingHandle<T> {
+    pub(crate) fn pending() -> Self {
+        Self {
+            state: Rc::new(RefCell::new(LoadState::Pending)),
+        }
+    }
+
+    pub(crate) fn ready(handle: Handle<T>) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(LoadState::Ready(handle))),
+        }
+    }
+
+    pub(crate) fn resolve(&self, result: Result<Handle<T>, ResourceError>) {
+        *self.state.borrow_mut() = match result {
+            Ok(handle) => LoadState::Ready(handle),
+            Err(e) => LoadState::Failed(Rc::new(e)),
+        };
+    }
+
+    pub fn status(&self) -> LoadStatus {
+        match &*self.state.borrow() {
+            LoadState::Pending => LoadStatus::Pending,
+            LoadState::Ready(_) => LoadStatus::Ready,
+            LoadState::Failed(_) => LoadStatus::Failed,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.status() != LoadStatus::Pending
+    }
+
+    pub fn handle(&self) -> Option<Handle<T>> {
+        match &*self.state.borrow() {
+            LoadState::Ready(handle) => Some(handle.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn error(&self) -> Option<Rc<ResourceError>> {
+        match &*self.state.borrow() {
+            LoadState::Failed(e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum JobKind {
+    Texture,
+    Sound,
+}
+
+struct Job {
+    id: u64,
+    path: String,
+    kind: JobKind,
+}
+
+pub enum DecodedAsset {
+    Image(image::DynamicImage),
+    Sound(DecodedSound),
+}
+
+pub struct JobResult {
+    pub id: u64,
+    pub path: String,
+    pub result: Result<DecodedAsset, ResourceError>,
+}
+
+fn run_job(vfs: &Vfs, job: &Job) -> Result<DecodedAsset, ResourceError> {
+    let bytes = vfs.read(&job.path)?;
+
+    match job.kind {
+        JobKind::Texture => {
+            image::load_from_memory(&bytes)
+                .map(DecodedAsset::Image)
+                .map_err(|e| ResourceError::Decode {
+                    path: job.path.clone(),
+                    format: image_format_name(&bytes),
+                    details: e.to_string(),
+                })
+        }
+        JobKind::Sound => {
+            DecodedSound::decode(&bytes)
+                .map(DecodedAsset::Sound)
+                .map_err(|e| ResourceError::Decode {
+                    path: job.path.clone(),
+                    format: sound_format_name(&bytes),
+                    details: e.to_string(),
+                })
+        }
+    }
+}
+
+// Pool of worker threads reading and decoding files off the main thread
+pub struct AsyncLoader {
+    job_sender: Option<Sender<Job>>,
+    result_receiver: Receiver<JobResult>,
+    workers: Vec<JoinHandle<()>>,
+    next_job: u64,
+    queued: usize,
+    finished: usize,
+}
+
+impl AsyncLoader {
+    pub fn new (worker_count: usize, vfs: Arc<Vfs>) -> Self {
+        let (job_sender, job_receiver) = channel::<Job>();
+        let (result_sender, result_receiver) = channel::<JobResult>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count.max(1))
+            .map(|i| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                let vfs = vfs.clone();
+                std::thread::Builder::new()
+                    .name(format!("luna-loader-{}", i))
+                    .spawn(move || loop {
+                        let job = match job_receiver.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+
+                        let result = run_job(&vfs, &job);
+                        let sent = result_sender.send(JobResult {
+                            id: job.id,
+                            path: job.path,
+                            result,
+                        });
+                        if sent.is_err() { break; }
+                    })
+                    .expect("Could not spawn loader thread!")
+            })
+            .collect();
+
+        Self {
+            job_sender: Some(job_sender),
+            result_receiver,
+            workers,
+            next_job: 0,
+            queued: 0,
+            finished: 0,
+        }
+    }
+
+    pub fn submit(&mut self, path: &str, kind: JobKind) -> u64 {
+        // Progress is tracked per batch, start a new one once everything has finished
+        if self.queued == self.finished {
+            self.queued = 0;
+            self.finished = 0;
+        }
+
+        let id = self.next_job;
+        self.next_job += 1;
+        self.queued += 1;
+
+        self.job_sender.as_ref().unwrap()
+            .send(Job { id, path: path.to_string(), kind })
+            .expect("Loader threads have stopped!");
+
+        id
+    }
+
+    pub fn poll(&mut self) -> Vec<JobResult> {
+        let results: Vec<JobResult> = self.result_receiver.try_iter().collect();
+        self.finished += results.len();
+        results
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.finished < self.queued
+    }
+
+    // Fraction of the current batch that has finished, 1.0 when idle
+    pub fn progress(&self) -> f32 {
+        if self.queued == 0 { return 1.0; }
+        self.finished as f32 / self.queued as f32
+    }
+}
+
+impl Drop for AsyncLoader {
+    fn drop(&mut self) {
+        // Closing the job channel stops the workers once they finish their current job
+        self.job_sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks};
+use gilrs::{Axis, Button, EventType, Gilrs};
+use serde::{Deserialize, Serialize};
+
+use crate::math::geo::V2;
+
+// Named after their position on the pad, South is A on an Xbox pad and Cross on a PlayStation pad
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+const BUTTON_COUNT: usize = 17;
+
+// Sticks are -1.0..1.0 with y pointing down like screen space, triggers are 0.0..1.0
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+const AXIS_COUNT: usize = 6;
+
+impl GamepadAxis {
+    // The other half of the stick this axis belongs to, dead zones are applied to both together
+    fn stick_pair(self) -> Option<(GamepadAxis, GamepadAxis)> {
+        match self {
+            GamepadAxis::LeftStickX | GamepadAxis::LeftStickY => Some((GamepadAxis::LeftStickX, GamepadAxis::LeftStickY)),
+            GamepadAxis::RightStickX | GamepadAxis::RightStickY => Some((GamepadAxis::RightStickX, GamepadAxis::RightStickY)),
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => None,
+        }
+    }
+}
+
+// Stable for as long as the pad stays connected
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct GamepadId(pub usize);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum GamepadEvent {
+    Connected { id: GamepadId, name: String },
+    Disconnected { id: GamepadId },
+    ButtonPressed { id: GamepadId, button: GamepadButton },
+    ButtonReleased { id: GamepadId, button: GamepadButton },
+    AxisChanged { id: GamepadId, axis: GamepadAxis, value: f32 },
+}
+
+// Input below the inner dead zone reads as 0.0 and above the outer one as fully pushed, what's
+// in between is rescaled to the full range
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct DeadZones {
+    pub stick: f32,
+    pub stick_outer: f32,
+    pub trigger: f32,
+}
+
+impl Default for DeadZones {
+    fn default() -> Self {
+        Self {
+            stick: 0.15,
+            stick_outer: 0.95,
+            trigger: 0.05,
+        }
+    }
+}
+
+fn rescale(value: f32, inner: f32, outer: f32) -> f32 {
+    if value <= inner {
+        0.0
+    } else {
+        ((value - inner) / (outer - inner).max(f32::EPSILON)).min(1.0)
+    }
+}
+
+#[derive(Clone)]
+pub enum ButtonAction {
+    Pressed(GamepadButton),
+    Released(GamepadButton),
+}
+
+#[derive(Clone)]
+pub struct GamepadState {
+    pub name: String,
+    pub button_actions: Vec<ButtonAction>,
+    pub button_held: [bool; BUTTON_COUNT],
+    // Raw values before dead zones are applied
+    pub axes: [f32; AXIS_COUNT],
+    pub axes_prev: [f32; AXIS_COUNT],
+}
+
+impl GamepadState {
+    fn new (name: String) -> Self {
+        Self {
+            name,
+            button_actions: vec![],
+            button_held: [false; BUTTON_COUNT],
+            axes: [0.0; AXIS_COUNT],
+            axes_prev: [0.0; AXIS_COUNT],
+        }
+    }
+
+    pub fn button_pressed(&self, button: GamepadButton) -> bool {
+        self.button_actions.iter().any(|action| matches!(action, ButtonAction::Pressed(b) if *b == button))
+    }
+
+    pub fn button_released(&self, button: GamepadButton) -> bool {
+        self.button_actions.iter().any(|action| matches!(action, ButtonAction::Released(b) if *b == button))
+    }
+
+    pub fn button_held(&self, button: GamepadButton) -> bool {
+        self.button_held[button as usize]
+    }
+
+    fn axis_from(&self, axes: &[f32; AXIS_COUNT], axis: GamepadAxis, dead_zones: &DeadZones) -> f32 {
+        match axis.stick_pair() {
+            Some((x, y)) => {
+                let stick = V2::new(axes[x as usize], axes[y as usize]);
+                let length = (stick.x * stick.x + stick.y * stick.y).sqrt();
+                if length == 0.0 {
+                    return 0.0;
+                }
+                let scale = rescale(length, dead_zones.stick, dead_zones.stick_outer) / length;
+                axes[axis as usize] * scale
+            }
+            None => rescale(axes[axis as usize], dead_zones.trigger, 1.0),
+        }
+    }
+
+    pub fn axis(&self, axis: GamepadAxis, dead_zones: &DeadZones) -> f32 {
+        self.axis_from(&self.axes, axis, dead_zones)
+    }
+
+    // Value at the end of the previous frame, to detect an axis crossing a threshold
+    pub fn axis_prev(&self, axis: GamepadAxis, dead_zones: &DeadZones) -> f32 {
+        self.axis_from(&self.axes_prev, axis, dead_zones)
+    }
+}
+
+// Every connected pad, updated from `GamepadEvent`s with the same per frame flush as `Input`
+#[derive(Clone, Default)]
+pub struct Gamepads {
+    pub pads: BTreeMap<GamepadId, GamepadState>,
+    pub connected: Vec<GamepadId>,
+    pub disconnected: Vec<GamepadId>,
+    pub dead_zones: DeadZones,
+}
+
+impl Gamepads {
+    pub fn flush(&mut self) {
+        self.connected.clear();
+        self.disconnected.clear();
+        for pad in self.pads.values_mut() {
+            pad.button_actions.clear();
+            pad.axes_prev = pad.axes;
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &GamepadEvent) {
+        match event {
+            GamepadEvent::Connected { id, name } => {
+                self.pads.insert(*id, GamepadState::new(name.clone()));
+                self.connected.push(*id);
+            }
+            GamepadEvent::Disconnected { id } => {
+                if self.pads.remove(id).is_some() {
+                    self.disconnected.push(*id);
+                }
+            }
+            GamepadEvent::ButtonPressed { id, button } => {
+                if let Some(pad) = self.pads.get_mut(id) {
+                    if !pad.button_held[*button as usize] {
+                        pad.button_actions.push(ButtonAction::Pressed(*button));
+                    }
+                    pad.button_held[*button as usize] = true;
+                }
+            }
+            GamepadEvent::ButtonReleased { id, button } => {
+                if let Some(pad) = self.pads.get_mut(id) {
+                    pad.button_held[*button as usize] = false;
+                    pad.button_actions.push(ButtonAction::Released(*button));
+                }
+            }
+            GamepadEvent::AxisChanged { id, axis, value } => {
+                if let Some(pad) = self.pads.get_mut(id) {
+                    pad.axes[*axis as usize] = value.clamp(-1.0, 1.0);
+                }
+            }
+        }
+    }
+
+    // A single pad, or every connected pad when None
+    pub(crate) fn matching(&self, pad: Option<GamepadId>) -> impl Iterator<Item = &GamepadState> {
+        self.pads.iter()
+            .filter(move |(id, _)| pad.map_or(true, |pad| pad == **id))
+            .map(|(_, state)| state)
+    }
+}
+
+fn convert_button(button: Button) -> Option<GamepadButton> {
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftStick,
+        Button::RightThumb => GamepadButton::RightStick,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+// gilrs sticks point up, ours point down
+fn convert_axis(axis: Axis, value: f32) -> Option<(GamepadAxis, f32)> {
+    Some(match axis {
+        Axis::LeftStickX => (GamepadAxis::LeftStickX, value),
+        Axis::LeftStickY => (GamepadAxis::LeftStickY, -value),
+        Axis::RightStickX => (GamepadAxis::RightStickX, value),
+        Axis::RightStickY => (GamepadAxis::RightStickY, -value),
+        _ => return None,
+    })
+}
+
+// Reads pads through gilrs and turns its events into `GamepadEvent`s
+pub(crate) struct GamepadBackend {
+    gilrs: Gilrs,
+    ids: HashMap<GamepadId, gilrs::GamepadId>,
+    pending: Vec<GamepadEvent>,
+    rumbles: Vec<(Effect, Instant)>,
+}
+
+impl GamepadBackend {
+    pub(crate) fn new () -> Result<Self, gilrs::Error> {
+        let gilrs = Gilrs::new()?;
+        let mut ids = HashMap::new();
+
+        // gilrs only sends connect events for pads plugged in later
+        let pending = gilrs.gamepads()
+            .map(|(gilrs_id, gamepad)| {
+                let id = GamepadId(gilrs_id.into());
+                ids.insert(id, gilrs_id);
+                GamepadEvent::Connected { id, name: gamepad.name().to_string() }
+            })
+            .collect();
+
+        Ok(Self {
+            gilrs,
+            ids,
+            pending,
+            rumbles: vec![],
+        })
+    }
+
+    pub(crate) fn poll(&mut self) -> Vec<GamepadEvent> {
+        let mut events = std::mem::take(&mut self.pending);
+
+        while let Some(event) = self.gilrs.next_event() {
+            let id = GamepadId(event.id.into());
+            let converted = match event.event {
+                EventType::Connected => {
+                    self.ids.insert(id, event.id);
+                    let name = self.gilrs.gamepad(event.id).name().to_string();
+                    Some(GamepadEvent::Connected { id, name })
+                }
+                EventType::Disconnected => {
+                    self.ids.remove(&id);
+                    Some(GamepadEvent::Disconnected { id })
+                }
+                EventType::ButtonPressed(button, _) => convert_button(button)
+                    .map(|button| GamepadEvent::ButtonPressed { id, button }),
+                EventType::ButtonReleased(button, _) => convert_button(button)
+                    .map(|button| GamepadEvent::ButtonReleased { id, button }),
+                // Analog triggers are reported as buttons with a value
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) =>
+                    Some(GamepadEvent::AxisChanged { id, axis: GamepadAxis::LeftTrigger, value }),
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) =>
+                    Some(GamepadEvent::AxisChanged { id, axis: GamepadAxis::RightTrigger, value }),
+                EventType::AxisChanged(axis, value, _) => convert_axis(axis, value)
+                    .map(|(axis, value)| GamepadEvent::AxisChanged { id, axis, value }),
+                _ => None,
+            };
+            events.extend(converted);
+        }
+
+        let now = Instant::now();
+        self.rumbles.retain(|(_, end)| *end > now);
+
+        events
+    }
+
+    // Strong is the low frequency motor, weak the high frequency one, both 0.0..1.0
+    pub(crate) fn rumble(&mut self, id: GamepadId, strong: f32, weak: f32, duration: Duration) -> Result<(), gilrs::ff::Error> {
+        let gilrs_id = match self.ids.get(&id) {
+            Some(gilrs_id) => *gilrs_id,
+            None => return Ok(()),
+        };
+
+        let scheduling = Replay {
+            play_for: Ticks::from_ms(duration.as_millis().min(u32::MAX as u128) as u32),
+            ..Default::default()
+        };
+        let magnitude = |value: f32| (value.clamp(0.0, 1.0) * u16::MAX as f32) as u16;
+
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong { magnitude: magnitude(strong) },
+                scheduling,
+                ..Default::default()
+            })
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Weak { magnitude: magnitude(weak) },
+                scheduling,
+                ..Default::default()
+            })
+            .gamepads(&[gilrs_id])
+            .finish(&mut self.gilrs)?;
+        effect.play()?;
+
+        // Dropping the effect stops it, keep it around until it is done
+        self.rumbles.push((effect, Instant::now() + duration));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAD: GamepadId = GamepadId(0);
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    fn connected() -> Gamepads {
+        let mut gamepads = Gamepads::default();
+        gamepads.handle_event(&GamepadEvent::Connected { id: PAD, name: "Test pad".to_string() });
+        gamepads
+    }
+
+    fn set_axis(gamepads: &mut Gamepads, axis: GamepadAxis, value: f32) {
+        gamepads.handle_event(&GamepadEvent::AxisChanged { id: PAD, axis, value });
+    }
+
+    #[test]
+    fn rescale_dead_zones() {
+        assert_eq!(rescale(0.1, 0.2, 0.8), 0.0);
+        assert_eq!(rescale(0.2, 0.2, 0.8), 0.0);
+        assert!(close(rescale(0.5, 0.2, 0.8), 0.5));
+        assert_eq!(rescale(0.8, 0.2, 0.8), 1.0);
+        assert_eq!(rescale(1.0, 0.2, 0.8), 1.0);
+        assert_eq!(rescale(-0.5, 0.2, 0.8), 0.0);
+        // Inner and outer on top of each other act as a threshold instead of dividing by zero
+        assert_eq!(rescale(0.6, 0.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn stick_dead_zone_is_radial() {
+        let mut gamepads = connected();
+        let dead_zones = DeadZones::default();
+
+        set_axis(&mut gamepads, GamepadAxis::LeftStickX, 0.1);
+        set_axis(&mut gamepads, GamepadAxis::LeftStickY, 0.1);
+        let pad = &gamepads.pads[&PAD];
+        assert_eq!(pad.axis(GamepadAxis::LeftStickX, &dead_zones), 0.0);
+        assert_eq!(pad.axis(GamepadAxis::LeftStickY, &dead_zones), 0.0);
+
+        // Past the inner zone on the diagonal even though each axis alone isn't
+        set_axis(&mut gamepads, GamepadAxis::LeftStickX, 0.12);
+        set_axis(&mut gamepads, GamepadAxis::LeftStickY, -0.12);
+        let pad = &gamepads.pads[&PAD];
+        let (x, y) = (pad.axis(GamepadAxis::LeftStickX, &dead_zones), pad.axis(GamepadAxis::LeftStickY, &dead_zones));
+        assert!(x > 0.0 && close(x, -y));
+        assert!(close((x * x + y * y).sqrt(), rescale((0.12f32 * 0.12 * 2.0).sqrt(), 0.15, 0.95)));
+
+        set_axis(&mut gamepads, GamepadAxis::LeftStickX, 0.97);
+        set_axis(&mut gamepads, GamepadAxis::LeftStickY, 0.0);
+        assert!(close(gamepads.pads[&PAD].axis(GamepadAxis::LeftStickX, &dead_zones), 1.0));
+    }
+
+    #[test]
+    fn trigger_dead_zone() {
+        let mut gamepads = connected();
+        let dead_zones = DeadZones::default();
+
+        set_axis(&mut gamepads, GamepadAxis::LeftTrigger, 0.04);
+        assert_eq!(gamepads.pads[&PAD].axis(GamepadAxis::LeftTrigger, &dead_zones), 0.0);
+        set_axis(&mut gamepads, GamepadAxis::LeftTrigger, 1.0);
+        assert_eq!(gamepads.pads[&PAD].axis(GamepadAxis::LeftTrigger, &dead_zones), 1.0);
+        // Raw values are clamped
+        set_axis(&mut gamepads, GamepadAxis::LeftTrigger, 3.0);
+        assert_eq!(gamepads.pads[&PAD].axes[GamepadAxis::LeftTrigger as usize], 1.0);
+    }
+
+    #[test]
+    fn buttons_and_axes_across_flushes() {
+        let mut gamepads = connected();
+        let dead_zones = DeadZones::default();
+        assert_eq!(gamepads.connected, [PAD]);
+
+        gamepads.handle_event(&GamepadEvent::ButtonPressed { id: PAD, button: GamepadButton::South });
+        set_axis(&mut gamepads, GamepadAxis::RightTrigger, 1.0);
+        let pad = &gamepads.pads[&PAD];
+        assert!(pad.button_pressed(GamepadButton::South) && pad.button_held(GamepadButton::South));
+        assert_eq!(pad.axis_prev(GamepadAxis::RightTrigger, &dead_zones), 0.0);
+
+        gamepads.flush();
+        assert!(gamepads.connected.is_empty());
+        let pad = &gamepads.pads[&PAD];
+        assert!(!pad.button_pressed(GamepadButton::South) && pad.button_held(GamepadButton::South));
+        assert_eq!(pad.axis_prev(GamepadAxis::RightTrigger, &dead_zones), 1.0);
+
+        gamepads.handle_event(&GamepadEvent::ButtonReleased { id: PAD, button: GamepadButton::South });
+        assert!(gamepads.pads[&PAD].button_released(GamepadButton::South));
+
+        gamepads.handle_event(&GamepadEvent::Disconnected { id: PAD });
+        assert!(gamepads.pads.is_empty());
+        assert_eq!(gamepads.disconnected, [PAD]);
+    }
+}
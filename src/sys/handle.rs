@@ -0,0 +1,283 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+// Index into a `ResourcePool`. The generation is bumped every time a slot is freed, so an id
+// that outlived its resource never aliases whatever is loaded into the slot next.
+pub struct ResourceId<T> {
+    index: u32,
+    generation: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ResourceId<T> {
+    pub const fn from_raw_parts(index: u32, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+            marker: PhantomData,
+        }
+    }
+
+    // Placeholder for resources that haven't been inserted into a pool yet
+    pub const fn invalid() -> Self {
+        Self::from_raw_parts(u32::MAX, u32::MAX)
+    }
+
+    pub fn index(&self) -> u32 { self.index }
+    pub fn generation(&self) -> u32 { self.generation }
+}
+
+impl<T> Clone for ResourceId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ResourceId<T> {}
+
+impl<T> PartialEq for ResourceId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for ResourceId<T> {}
+
+impl<T> Hash for ResourceId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> Debug for ResourceId<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ResourceId({}, {})", self.index, self.generation)
+    }
+}
+
+type ReleaseQueue<T> = Rc<RefCell<Vec<ResourceId<T>>>>;
+
+struct HandleInner<T> {
+    id: ResourceId<T>,
+    released: ReleaseQueue<T>,
+}
+
+impl<T> Drop for HandleInner<T> {
+    fn drop(&mut self) {
+        self.released.borrow_mut().push(self.id);
+    }
+}
+
+// Reference counted owner of a resource. The resource is released by the pool's next
+// `collect_released` once every clone of the handle has been dropped.
+pub struct Handle<T> {
+    inner: Rc<HandleInner<T>>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> ResourceId<T> {
+        self.inner.id
+    }
+
+    pub fn ref_count(&self) -> usize {
+        Rc::strong_count(&self.inner)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle")
+            .field("id", &self.inner.id)
+            .field("ref_count", &self.ref_count())
+            .finish()
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+    handle: Weak<HandleInner<T>>,
+    path: Option<PathBuf>,
+    persistent: bool,
+}
+
+pub struct ResourcePool<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    paths: HashMap<PathBuf, ResourceId<T>>,
+    released: ReleaseQueue<T>,
+}
+
+impl<T> Default for ResourcePool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ResourcePool<T> {
+    pub fn new () -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            paths: HashMap::new(),
+            released: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    fn insert_slot(&mut self, value: T, path: Option<PathBuf>, persistent: bool) -> ResourceId<T> {
+        if let Some(path) = &path {
+            self.paths.remove(path);
+        }
+
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                slot.handle = Weak::new();
+                slot.path = path.clone();
+                slot.persistent = persistent;
+                ResourceId::from_raw_parts(index, slot.generation)
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                    handle: Weak::new(),
+                    path: path.clone(),
+                    persistent,
+                });
+                ResourceId::from_raw_parts(self.slots.len() as u32 - 1, 0)
+            }
+        };
+
+        if let Some(path) = path {
+            self.paths.insert(path, id);
+        }
+
+        id
+    }
+
+    pub fn insert(&mut self, value: T, path: Option<PathBuf>) -> ResourceId<T> {
+        self.insert_slot(value, path, false)
+    }
+
+    // Persistent resources are never released or unloaded, used for built in resources
+    pub fn insert_persistent(&mut self, value: T) -> ResourceId<T> {
+        self.insert_slot(value, None, true)
+    }
+
+    fn slot(&self, id: ResourceId<T>) -> Option<&Slot<T>> {
+        self.slots.get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation && slot.value.is_some())
+    }
+
+    fn slot_mut(&mut self, id: ResourceId<T>) -> Option<&mut Slot<T>> {
+        self.slots.get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation && slot.value.is_some())
+    }
+
+    // Returns a new strong handle to the resource, sharing the reference count with every other
+    // handle that is still alive
+    pub fn handle(&mut self, id: ResourceId<T>) -> Option<Handle<T>> {
+        let released = self.released.clone();
+        let slot = self.slot_mut(id)?;
+
+        if let Some(inner) = slot.handle.upgrade() {
+            return Some(Handle { inner });
+        }
+
+        let inner = Rc::new(HandleInner { id, released });
+        slot.handle = Rc::downgrade(&inner);
+        Some(Handle { inner })
+    }
+
+    pub fn get(&self, id: ResourceId<T>) -> Option<&T> {
+        self.slot(id).and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: ResourceId<T>) -> Option<&mut T> {
+        self.slot_mut(id).and_then(|slot| slot.value.as_mut())
+    }
+
+    pub fn contains(&self, id: ResourceId<T>) -> bool {
+        self.slot(id).is_some()
+    }
+
+    pub fn path(&self, id: ResourceId<T>) -> Option<&Path> {
+        self.slot(id).and_then(|slot| slot.path.as_deref())
+    }
+
+    pub fn find_path(&self, path: &Path) -> Option<ResourceId<T>> {
+        self.paths.get(path).copied()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = (&Path, ResourceId<T>)> {
+        self.paths.iter().map(|(path, &id)| (path.as_path(), id))
+    }
+
+    // Frees the resource immediately, even if handles to it are still alive
+    pub fn remove(&mut self, id: ResourceId<T>) -> Option<T> {
+        let slot = self.slot_mut(id)?;
+        if slot.persistent { return None; }
+
+        let value = slot.value.take();
+        let path = slot.path.take();
+        slot.handle = Weak::new();
+        slot.generation = slot.generation.wrapping_add(1);
+
+        if let Some(path) = path {
+            self.paths.remove(&path);
+        }
+        self.free.push(id.index);
+
+        value
+    }
+
+    // Frees every resource whose last handle has been dropped since the previous call
+    pub fn collect_released(&mut self) -> Vec<T> {
+        let released: Vec<ResourceId<T>> = self.released.borrow_mut().drain(..).collect();
+
+        let mut freed = vec![];
+        for id in released {
+            // A new handle may have been handed out after the old ones were dropped
+            let still_owned = self.slot(id).map_or(true, |slot| slot.handle.strong_count() > 0);
+            if still_owned { continue; }
+
+            if let Some(value) = self.remove(id) {
+                freed.push(value);
+            }
+        }
+        freed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ResourceId<T>, &T)> {
+        self.slots.iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                slot.value.as_ref()
+                    .map(|value| (ResourceId::from_raw_parts(index as u32, slot.generation), value))
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sys::resource_manager::{ShaderID, SoundID, TextureID};
+
+#[derive(Clone, Debug)]
+pub enum ReloadEvent {
+    Texture(TextureID),
+    Sound(SoundID),
+    Shader(ShaderID),
+    Failed { path: PathBuf, error: String },
+}
+
+// Polls the modification time of every file backed resource
+pub struct HotReloader {
+    poll_interval: Duration,
+    last_poll: Instant,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl HotReloader {
+    pub fn new (poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            last_poll: Instant::now(),
+            modified: HashMap::new(),
+        }
+    }
+
+    // True once every poll interval
+    pub fn should_poll(&mut self) -> bool {
+        let now = Instant::now();
+        if now - self.last_poll < self.poll_interval { return false; }
+        self.last_poll = now;
+        true
+    }
+
+    // Returns true if the file changed since the last time it was checked. Files seen for the
+    // first time are only recorded.
+    pub fn changed(&mut self, path: &Path) -> bool {
+        let modified = match std::fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return false,
+        };
+
+        match self.modified.insert(path.to_path_buf(), modified) {
+            Some(previous) => previous != modified,
+            None => false,
+        }
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.modified.remove(path);
+    }
+}
@@ -1,6 +1,61 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
 use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
 
 use crate::math::geo::V2;
+use crate::sys::gamepad::GamepadEvent;
+
+// Everything `InputSubsystem` reacts to, in a form that can be recorded and replayed
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum InputEvent {
+    Key { key: VirtualKeyCode, pressed: bool },
+    MouseButton { button: MouseButton, pressed: bool },
+    CursorMoved { x: f32, y: f32 },
+    // In lines
+    Scroll(f32),
+    Char(char),
+    Focused(bool),
+    Resized { width: u32, height: u32 },
+    ScaleFactorChanged(f64),
+    DroppedFile(PathBuf),
+    CloseRequested,
+    Destroyed,
+    Gamepad(GamepadEvent),
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        const PIXELS_PER_LINE: f64 = 38.0;
+
+        Some(match event {
+            WindowEvent::KeyboardInput { input, ..} => InputEvent::Key {
+                key: input.virtual_keycode?,
+                pressed: input.state == ElementState::Pressed,
+            },
+            WindowEvent::MouseInput { state, button, ..} => InputEvent::MouseButton {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            },
+            WindowEvent::CursorMoved { position, ..} => InputEvent::CursorMoved {
+                x: position.x as f32,
+                y: position.y as f32,
+            },
+            WindowEvent::MouseWheel { delta, ..} => match delta {
+                MouseScrollDelta::LineDelta(_, y) => InputEvent::Scroll(*y),
+                MouseScrollDelta::PixelDelta(delta) => InputEvent::Scroll((delta.y / PIXELS_PER_LINE) as f32),
+            },
+            WindowEvent::ReceivedCharacter(c) => InputEvent::Char(*c),
+            WindowEvent::Focused(focused) => InputEvent::Focused(*focused),
+            WindowEvent::Resized(size) => InputEvent::Resized { width: size.width, height: size.height },
+            WindowEvent::ScaleFactorChanged { scale_factor, ..} => InputEvent::ScaleFactorChanged(*scale_factor),
+            WindowEvent::DroppedFile(path) => InputEvent::DroppedFile(path.clone()),
+            WindowEvent::CloseRequested => InputEvent::CloseRequested,
+            WindowEvent::Destroyed => InputEvent::Destroyed,
+            _ => return None,
+        })
+    }
+}
 
 #[derive(Clone)]
 pub enum KeyAction {
@@ -55,70 +110,52 @@ impl Input {
     }
 
     pub fn handle_event(&mut self, event: &WindowEvent) {
-        match event {
-            WindowEvent::KeyboardInput { input, ..} => {
-                if let Some(keycode) = input.virtual_keycode {
-                    match input.state {
-                        ElementState::Pressed => {
-                            if !self.key_held[keycode as usize] {
-                                self.key_actions.push(KeyAction::Pressed(keycode));
-                            }
-                            self.key_held[keycode as usize] = true;
-                            if let VirtualKeyCode::Back = keycode {
-                                self.text.push(TextChar::Back);
-                            }
-                        }
-
-                        ElementState::Released => {
-                            self.key_held[keycode as usize] = false;
-                            self.key_actions.push(KeyAction::Released(keycode));
-                        }
-                    }
+        if let Some(event) = InputEvent::from_window_event(event) {
+            self.handle_input_event(&event);
+        }
+    }
+
+    pub fn handle_input_event(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::Key { key: keycode, pressed: true } => {
+                if !self.key_held[keycode as usize] {
+                    self.key_actions.push(KeyAction::Pressed(keycode));
+                }
+                self.key_held[keycode as usize] = true;
+                if let VirtualKeyCode::Back = keycode {
+                    self.text.push(TextChar::Back);
                 }
             }
 
-            WindowEvent::ReceivedCharacter(c) => {
-                let c = *c;
+            InputEvent::Key { key: keycode, pressed: false } => {
+                self.key_held[keycode as usize] = false;
+                self.key_actions.push(KeyAction::Released(keycode));
+            }
+
+            InputEvent::Char(c) => {
                 if c != '\x08' && c != '\r' && c != '\n' {
                     self.text.push(TextChar::Char(c));
                 }
             }
 
-            WindowEvent::CursorMoved { position, ..} => {
-                self.mouse_position = Some(V2::new(position.x as f32, position.y as f32));
+            InputEvent::CursorMoved { x, y } => {
+                self.mouse_position = Some(V2::new(x, y));
             }
 
-            WindowEvent::MouseInput {
-                state: ElementState::Pressed,
-                button,
-                ..
-            } => {
-                let button = mouse_button_to_int(button);
+            InputEvent::MouseButton { button, pressed: true } => {
+                let button = mouse_button_to_int(&button);
                 self.mouse_held[button] = true;
                 self.mouse_actions.push(MouseAction::Pressed(button));
             }
 
-            WindowEvent::MouseInput {
-                state: ElementState::Released,
-                button,
-                ..
-            } => {
-                let button = mouse_button_to_int(button);
+            InputEvent::MouseButton { button, pressed: false } => {
+                let button = mouse_button_to_int(&button);
                 self.mouse_held[button] = false;
                 self.mouse_actions.push(MouseAction::Released(button));
             }
 
-            WindowEvent::MouseWheel { delta, ..} => {
-                const PIXELS_PER_LINE: f64 = 38.0;
-
-                match delta {
-                    MouseScrollDelta::LineDelta(_, y) => {
-                        self.scroll_diff += y;
-                    }
-                    MouseScrollDelta::PixelDelta(delta) => {
-                        self.scroll_diff += (delta.y / PIXELS_PER_LINE) as f32;
-                    }
-                }
+            InputEvent::Scroll(lines) => {
+                self.scroll_diff += lines;
             }
 
             _ => {}
@@ -0,0 +1,382 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use crate::sys::gamepad::GamepadId;
+use crate::sys::input_map::{Binding, InputMap};
+
+#[derive(Copy, Clone, Debug)]
+pub struct HistoryEntry {
+    // Input time of the frame the event arrived in
+    pub time: Duration,
+    pub binding: Binding,
+    pub pressed: bool,
+    // Only set for gamepad bindings
+    pub pad: Option<GamepadId>,
+    // Consumed presses no longer count towards `pressed_within`
+    pub consumed: bool,
+}
+
+impl HistoryEntry {
+    fn matches(&self, binding: &Binding, pad: Option<GamepadId>) -> bool {
+        self.binding == *binding && (pad.is_none() || self.pad.is_none() || self.pad == pad)
+    }
+
+    fn belongs_to(&self, map: &InputMap, action: &str) -> bool {
+        map.action_bindings(action).iter().any(|binding| self.matches(binding, map.gamepad))
+    }
+}
+
+// Presses and releases of the last `length` of input time, oldest first
+#[derive(Clone)]
+pub struct InputHistory {
+    entries: VecDeque<HistoryEntry>,
+    pub length: Duration,
+}
+
+impl Default for InputHistory {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            length: Duration::from_secs(2),
+        }
+    }
+}
+
+impl InputHistory {
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn record(&mut self, time: Duration, binding: Binding, pressed: bool, pad: Option<GamepadId>) {
+        self.entries.push_back(HistoryEntry {
+            time,
+            binding,
+            pressed,
+            pad,
+            consumed: false,
+        });
+    }
+
+    // Presses of bindings that are still held are kept however old they are, so sequences
+    // still see them as held, eg. a charge move held for longer than the history length
+    pub(crate) fn prune(&mut self, now: Duration) {
+        match self.entries.front() {
+            Some(entry) if now.saturating_sub(entry.time) > self.length => {}
+            _ => return,
+        }
+
+        let mut released: HashSet<(Binding, Option<GamepadId>)> = HashSet::new();
+        let mut keep: Vec<bool> = self.entries.iter()
+            .rev()
+            .map(|entry| {
+                let recent = now.saturating_sub(entry.time) <= self.length;
+                if !entry.pressed {
+                    released.insert((entry.binding, entry.pad));
+                    return recent;
+                }
+                recent || !released.contains(&(entry.binding, entry.pad))
+            })
+            .collect();
+        keep.reverse();
+
+        let mut keep = keep.into_iter();
+        self.entries.retain(|_| keep.next().unwrap());
+    }
+
+    pub(crate) fn pressed_within(&self, map: &InputMap, action: &str, now: Duration, window: Duration) -> bool {
+        self.entries.iter()
+            .rev()
+            .take_while(|entry| now.saturating_sub(entry.time) <= window)
+            .any(|entry| entry.pressed && !entry.consumed && entry.belongs_to(map, action))
+    }
+
+    pub(crate) fn consume(&mut self, map: &InputMap, action: &str) {
+        for entry in self.entries.iter_mut() {
+            if entry.pressed && entry.belongs_to(map, action) {
+                entry.consumed = true;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SequenceStep {
+    // Held together, the step happens when the last of them is pressed
+    pub actions: Vec<String>,
+    // Longest allowed time since the previous step, ignored for the first step
+    pub max_delay: Duration,
+}
+
+// A move made of steps in order, eg. down, down + forward, forward + punch
+#[derive(Clone, Debug, Default)]
+pub struct InputSequence {
+    pub steps: Vec<SequenceStep>,
+    // How long the sequence keeps matching after its last step, zero only matches on that frame
+    pub buffer: Duration,
+}
+
+impl InputSequence {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, actions: &[&str], max_delay: Duration) -> Self {
+        self.steps.push(SequenceStep {
+            actions: actions.iter().map(|action| action.to_string()).collect(),
+            max_delay,
+        });
+        self
+    }
+
+    pub fn with_buffer(mut self, buffer: Duration) -> Self {
+        self.buffer = buffer;
+        self
+    }
+}
+
+// Which actions were pressed by a history entry and which were held right after it
+struct PressSnapshot<'a> {
+    time: Duration,
+    pressed: Vec<&'a str>,
+    held: Vec<&'a str>,
+}
+
+impl PressSnapshot<'_> {
+    fn completes(&self, step: &SequenceStep) -> bool {
+        step.actions.iter().any(|action| self.pressed.contains(&action.as_str()))
+            && step.actions.iter().all(|action| self.held.contains(&action.as_str()))
+    }
+}
+
+pub(crate) fn sequence_matches(history: &InputHistory, map: &InputMap, sequence: &InputSequence, now: Duration) -> bool {
+    let last_step = match sequence.steps.last() {
+        Some(step) => step,
+        None => return false,
+    };
+
+    let mut actions: Vec<&str> = sequence.steps.iter()
+        .flat_map(|step| step.actions.iter().map(|action| action.as_str()))
+        .collect();
+    actions.sort_unstable();
+    actions.dedup();
+
+    // Replay the history to know what was held at every press
+    let mut held: HashSet<(Binding, Option<GamepadId>)> = HashSet::new();
+    let mut snapshots: Vec<PressSnapshot> = vec![];
+    for entry in history.entries.iter() {
+        if !entry.pressed {
+            held.remove(&(entry.binding, entry.pad));
+            continue;
+        }
+        held.insert((entry.binding, entry.pad));
+
+        let pressed: Vec<&str> = actions.iter()
+            .copied()
+            .filter(|action| entry.belongs_to(map, action))
+            .collect();
+        if pressed.is_empty() {
+            continue;
+        }
+
+        let held_actions = actions.iter()
+            .copied()
+            .filter(|action| map.action_bindings(action).iter().any(|binding| {
+                held.iter().any(|(held_binding, pad)| held_binding == binding
+                    && (map.gamepad.is_none() || pad.is_none() || *pad == map.gamepad))
+            }))
+            .collect();
+
+        snapshots.push(PressSnapshot {
+            time: entry.time,
+            pressed,
+            held: held_actions,
+        });
+    }
+
+    // Walk back from every press that could be the final step, taking the latest press that fits
+    // each earlier step
+    'candidates: for last in (0..snapshots.len()).rev() {
+        if now.saturating_sub(snapshots[last].time) > sequence.buffer {
+            break;
+        }
+        if !snapshots[last].completes(last_step) {
+            continue;
+        }
+
+        let mut next = last;
+        for (i, step) in sequence.steps.iter().enumerate().rev().skip(1) {
+            let max_delay = sequence.steps[i + 1].max_delay;
+            let next_time = snapshots[next].time;
+            let found = (0..next).rev()
+                .take_while(|j| next_time.saturating_sub(snapshots[*j].time) <= max_delay)
+                .find(|j| snapshots[*j].completes(step));
+
+            match found {
+                Some(j) => next = j,
+                None => continue 'candidates,
+            }
+        }
+        return true;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use winit::event::VirtualKeyCode;
+
+    use crate::sys::input_history::InputSequence;
+    use crate::sys::input_map::{Binding, InputMap};
+    use crate::sys::input_subsystem::InputSubsystem;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn input() -> InputSubsystem {
+        let mut input = InputSubsystem::new();
+        input.set_fixed_timestep(Some(FRAME));
+        input.set_input_map(InputMap::new()
+            .with_action("jump", &[Binding::Key(VirtualKeyCode::Space)])
+            .with_action("down", &[Binding::Key(VirtualKeyCode::S)])
+            .with_action("forward", &[Binding::Key(VirtualKeyCode::D)])
+            .with_action("punch", &[Binding::Key(VirtualKeyCode::J)]));
+        input
+    }
+
+    fn fireball(max_delay: Duration) -> InputSequence {
+        InputSequence::new()
+            .then(&["down"], Duration::ZERO)
+            .then(&["down", "forward"], max_delay)
+            .then(&["forward", "punch"], max_delay)
+    }
+
+    // Down, down + forward, forward + punch, one step per frame
+    fn input_fireball(input: &mut InputSubsystem, frames_between: u32) {
+        input.inject_key_down(VirtualKeyCode::S);
+        for _ in 0..frames_between { input.flush(); }
+        input.inject_key_down(VirtualKeyCode::D);
+        for _ in 0..frames_between { input.flush(); }
+        input.inject_key_up(VirtualKeyCode::S);
+        input.inject_key_down(VirtualKeyCode::J);
+    }
+
+    #[test]
+    fn fixed_timestep_time() {
+        let mut input = input();
+        assert_eq!(input.time(), Duration::ZERO);
+        input.flush();
+        input.flush();
+        assert_eq!(input.time(), FRAME * 2);
+    }
+
+    #[test]
+    fn buffered_press() {
+        let mut input = input();
+        input.inject_key_tap(VirtualKeyCode::Space);
+
+        for _ in 0..3 { input.flush(); }
+        assert!(input.pressed_within("jump", Duration::from_millis(50)));
+        assert!(!input.pressed_within("jump", Duration::from_millis(40)));
+        assert!(!input.pressed_within("down", Duration::from_millis(50)));
+
+        input.flush();
+        assert!(!input.pressed_within("jump", Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn consumed_press() {
+        let mut input = input();
+        input.inject_key_tap(VirtualKeyCode::Space);
+        input.flush();
+        assert!(input.pressed_within("jump", Duration::from_millis(50)));
+
+        input.consume_action("jump");
+        assert!(!input.pressed_within("jump", Duration::from_millis(50)));
+
+        input.inject_key_tap(VirtualKeyCode::Space);
+        assert!(input.pressed_within("jump", Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn history_is_pruned() {
+        let mut input = input();
+        input.set_history_length(FRAME * 2);
+        input.inject_key_tap(VirtualKeyCode::Space);
+        assert_eq!(input.history().entries().count(), 2);
+
+        for _ in 0..3 { input.flush(); }
+        assert_eq!(input.history().entries().count(), 0);
+    }
+
+    #[test]
+    fn sequence_in_time() {
+        let mut input = input();
+        let sequence = fireball(FRAME * 2);
+
+        input_fireball(&mut input, 2);
+        assert!(input.sequence_matched(&sequence));
+
+        // Without a buffer it only matches on the frame of the last step
+        input.flush();
+        assert!(!input.sequence_matched(&sequence));
+        assert!(input.sequence_matched(&sequence.clone().with_buffer(FRAME)));
+    }
+
+    #[test]
+    fn sequence_too_slow() {
+        let mut input = input();
+        input_fireball(&mut input, 3);
+        assert!(!input.sequence_matched(&fireball(FRAME * 2)));
+    }
+
+    #[test]
+    fn sequence_needs_held_actions() {
+        let mut input = input();
+        let sequence = fireball(FRAME * 2);
+
+        // Down released before forward, so the second step never had both held
+        input.inject_key_tap(VirtualKeyCode::S);
+        input.flush();
+        input.inject_key_down(VirtualKeyCode::D);
+        input.flush();
+        input.inject_key_down(VirtualKeyCode::J);
+        assert!(!input.sequence_matched(&sequence));
+    }
+
+    #[test]
+    fn sequence_out_of_order() {
+        let mut input = input();
+        input.inject_key_down(VirtualKeyCode::D);
+        input.flush();
+        input.inject_key_down(VirtualKeyCode::S);
+        input.flush();
+        input.inject_key_down(VirtualKeyCode::J);
+        assert!(!input.sequence_matched(&fireball(FRAME * 2)));
+    }
+
+    #[test]
+    fn held_longer_than_history() {
+        let mut input = input();
+        input.set_history_length(FRAME * 2);
+        let charge = InputSequence::new().then(&["down", "punch"], Duration::ZERO);
+
+        input.inject_key_down(VirtualKeyCode::S);
+        for _ in 0..5 { input.flush(); }
+        assert_eq!(input.history().entries().count(), 1);
+
+        input.inject_key_down(VirtualKeyCode::J);
+        assert!(input.sequence_matched(&charge));
+
+        // Once released the press is pruned like any other
+        input.inject_key_up(VirtualKeyCode::S);
+        input.inject_key_up(VirtualKeyCode::J);
+        for _ in 0..3 { input.flush(); }
+        assert_eq!(input.history().entries().count(), 0);
+    }
+}
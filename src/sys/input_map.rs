@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::math::geo::V2;
+use crate::sys::gamepad::{DeadZones, GamepadId, GamepadState, Gamepads};
+use crate::sys::input_subsystem::InputSubsystem;
+
+pub use crate::sys::gamepad::{GamepadAxis, GamepadButton};
+
+// An axis pushed past this counts as a held button
+pub(crate) const AXIS_BUTTON_THRESHOLD: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton),
+    // An analog axis used as a button, held while pushed far enough towards the given side
+    GamepadAxis { axis: GamepadAxis, positive: bool },
+}
+
+impl Binding {
+    fn pressed(&self, input: &InputSubsystem, pad: Option<GamepadId>) -> bool {
+        match *self {
+            Binding::Key(key) => input.key_pressed(key),
+            Binding::Mouse(button) => input.mouse_pressed(button),
+            Binding::GamepadButton(button) => any_pad(input, pad, |state, _| state.button_pressed(button)),
+            Binding::GamepadAxis { axis, positive } => any_pad(input, pad, |state, dead_zones| {
+                let (prev, now) = axis_side(state, dead_zones, axis, positive);
+                prev < AXIS_BUTTON_THRESHOLD && now >= AXIS_BUTTON_THRESHOLD
+            }),
+        }
+    }
+
+    fn released(&self, input: &InputSubsystem, pad: Option<GamepadId>) -> bool {
+        match *self {
+            Binding::Key(key) => input.key_released(key),
+            Binding::Mouse(button) => input.mouse_released(button),
+            Binding::GamepadButton(button) => any_pad(input, pad, |state, _| state.button_released(button)),
+            Binding::GamepadAxis { axis, positive } => any_pad(input, pad, |state, dead_zones| {
+                let (prev, now) = axis_side(state, dead_zones, axis, positive);
+                prev >= AXIS_BUTTON_THRESHOLD && now < AXIS_BUTTON_THRESHOLD
+            }),
+        }
+    }
+
+    // 0.0 to 1.0, digital inputs are either
+    fn value(&self, input: &InputSubsystem, pad: Option<GamepadId>) -> f32 {
+        match *self {
+            Binding::Key(key) => if input.key_held(key) { 1.0 } else { 0.0 },
+            Binding::Mouse(button) => if input.mouse_held(button) { 1.0 } else { 0.0 },
+            Binding::GamepadButton(button) => {
+                if any_pad(input, pad, |state, _| state.button_held(button)) { 1.0 } else { 0.0 }
+            }
+            Binding::GamepadAxis { axis, positive } => pads(input, pad)
+                .map(|(state, dead_zones)| axis_side(state, dead_zones, axis, positive).1)
+                .fold(0.0, f32::max),
+        }
+    }
+
+    fn held(&self, input: &InputSubsystem, pad: Option<GamepadId>) -> bool {
+        self.value(input, pad) >= AXIS_BUTTON_THRESHOLD
+    }
+}
+
+fn pads(input: &InputSubsystem, pad: Option<GamepadId>) -> impl Iterator<Item = (&GamepadState, &DeadZones)> {
+    input.gamepad_input()
+        .into_iter()
+        .flat_map(move |gamepads: &Gamepads| gamepads.matching(pad).map(move |state| (state, &gamepads.dead_zones)))
+}
+
+fn any_pad(input: &InputSubsystem, pad: Option<GamepadId>, f: impl Fn(&GamepadState, &DeadZones) -> bool) -> bool {
+    pads(input, pad).any(|(state, dead_zones)| f(state, dead_zones))
+}
+
+// How far the axis is pushed towards one side, last frame and now
+fn axis_side(state: &GamepadState, dead_zones: &DeadZones, axis: GamepadAxis, positive: bool) -> (f32, f32) {
+    let sign = if positive { 1.0 } else { -1.0 };
+    (
+        (state.axis_prev(axis, dead_zones) * sign).max(0.0),
+        (state.axis(axis, dead_zones) * sign).max(0.0),
+    )
+}
+
+// A one dimensional axis in -1.0..1.0. Digital bindings push it fully to their side, analog
+// axes are added on top and the sum is clamped.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct AxisBinding {
+    pub negative: Vec<Binding>,
+    pub positive: Vec<Binding>,
+    pub analog: Vec<GamepadAxis>,
+}
+
+impl AxisBinding {
+    pub fn keys(negative: VirtualKeyCode, positive: VirtualKeyCode) -> Self {
+        Self {
+            negative: vec![Binding::Key(negative)],
+            positive: vec![Binding::Key(positive)],
+            analog: vec![],
+        }
+    }
+
+    pub fn with_negative(mut self, binding: Binding) -> Self {
+        self.negative.push(binding);
+        self
+    }
+
+    pub fn with_positive(mut self, binding: Binding) -> Self {
+        self.positive.push(binding);
+        self
+    }
+
+    pub fn with_analog(mut self, axis: GamepadAxis) -> Self {
+        self.analog.push(axis);
+        self
+    }
+
+    fn value(&self, input: &InputSubsystem, pad: Option<GamepadId>) -> f32 {
+        let side = |bindings: &[Binding]| bindings.iter()
+            .map(|binding| binding.value(input, pad))
+            .fold(0.0, f32::max);
+
+        // With several pads, the one pushed furthest wins
+        let analog: f32 = self.analog.iter()
+            .map(|axis| pads(input, pad)
+                .map(|(state, dead_zones)| state.axis(*axis, dead_zones))
+                .fold(0.0, |a: f32, b: f32| if b.abs() > a.abs() { b } else { a }))
+            .sum();
+
+        (side(&self.positive) - side(&self.negative) + analog).clamp(-1.0, 1.0)
+    }
+}
+
+// Two axes read together, eg. WASD or a stick for movement
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Axis2dBinding {
+    pub x: AxisBinding,
+    pub y: AxisBinding,
+}
+
+impl Axis2dBinding {
+    // Up is -y, matching screen space
+    pub fn wasd() -> Self {
+        Self {
+            x: AxisBinding::keys(VirtualKeyCode::A, VirtualKeyCode::D),
+            y: AxisBinding::keys(VirtualKeyCode::W, VirtualKeyCode::S),
+        }
+    }
+
+    pub fn arrows() -> Self {
+        Self {
+            x: AxisBinding::keys(VirtualKeyCode::Left, VirtualKeyCode::Right),
+            y: AxisBinding::keys(VirtualKeyCode::Up, VirtualKeyCode::Down),
+        }
+    }
+
+    pub fn left_stick() -> Self {
+        Self {
+            x: AxisBinding::default().with_analog(GamepadAxis::LeftStickX),
+            y: AxisBinding::default().with_analog(GamepadAxis::LeftStickY),
+        }
+    }
+
+    pub fn merge(mut self, other: Axis2dBinding) -> Self {
+        self.x.negative.extend(other.x.negative);
+        self.x.positive.extend(other.x.positive);
+        self.x.analog.extend(other.x.analog);
+        self.y.negative.extend(other.y.negative);
+        self.y.positive.extend(other.y.positive);
+        self.y.analog.extend(other.y.analog);
+        self
+    }
+}
+
+// Maps named actions and axes to physical inputs so games don't hardcode keys. The map is plain
+// data, save it with `to_ron` to let players rebind their controls.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct InputMap {
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    pub axes: BTreeMap<String, AxisBinding>,
+    pub axes_2d: BTreeMap<String, Axis2dBinding>,
+    // Pad that drives this map, any connected pad when None. Not saved since ids change between
+    // sessions.
+    #[serde(skip)]
+    pub gamepad: Option<GamepadId>,
+}
+
+impl InputMap {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn with_action(mut self, name: &str, bindings: &[Binding]) -> Self {
+        self.actions.entry(name.to_string()).or_default().extend_from_slice(bindings);
+        self
+    }
+
+    pub fn with_axis(mut self, name: &str, axis: AxisBinding) -> Self {
+        self.axes.insert(name.to_string(), axis);
+        self
+    }
+
+    pub fn with_axis_2d(mut self, name: &str, axis: Axis2dBinding) -> Self {
+        self.axes_2d.insert(name.to_string(), axis);
+        self
+    }
+
+    pub fn bind_action(&mut self, name: &str, binding: Binding) {
+        let bindings = self.actions.entry(name.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_action(&mut self, name: &str, binding: Binding) {
+        if let Some(bindings) = self.actions.get_mut(name) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+    // Replaces one binding of an action, eg. after the player picked a new key in the options
+    pub fn rebind_action(&mut self, name: &str, old: Binding, new: Binding) {
+        if let Some(bindings) = self.actions.get_mut(name) {
+            match bindings.iter().position(|b| *b == old) {
+                Some(i) => bindings[i] = new,
+                None => bindings.push(new),
+            }
+        }
+    }
+
+    pub fn action_bindings(&self, name: &str) -> &[Binding] {
+        self.actions.get(name).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    // Actions that are already bound to the input, to warn about conflicts when rebinding
+    pub fn actions_bound_to(&self, binding: Binding) -> impl Iterator<Item = &str> {
+        self.actions.iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(name, _)| name.as_str())
+    }
+
+    // Pressed this frame by any of its bindings, while none of the others were already held
+    pub fn action_pressed(&self, input: &InputSubsystem, name: &str) -> bool {
+        let bindings = self.action_bindings(name);
+        bindings.iter().any(|binding| binding.pressed(input, self.gamepad))
+            && !bindings.iter().any(|binding| binding.held(input, self.gamepad) && !binding.pressed(input, self.gamepad))
+    }
+
+    pub fn action_held(&self, input: &InputSubsystem, name: &str) -> bool {
+        self.action_bindings(name).iter().any(|binding| binding.held(input, self.gamepad))
+    }
+
+    // Released this frame with no other binding still holding it
+    pub fn action_released(&self, input: &InputSubsystem, name: &str) -> bool {
+        let bindings = self.action_bindings(name);
+        bindings.iter().any(|binding| binding.released(input, self.gamepad))
+            && !bindings.iter().any(|binding| binding.held(input, self.gamepad))
+    }
+
+    pub fn axis_value(&self, input: &InputSubsystem, name: &str) -> f32 {
+        self.axes.get(name).map_or(0.0, |axis| axis.value(input, self.gamepad))
+    }
+
+    // Clamped to length 1.0 so diagonals aren't faster
+    pub fn axis_2d(&self, input: &InputSubsystem, name: &str) -> V2 {
+        let axis = match self.axes_2d.get(name) {
+            Some(axis) => axis,
+            None => return V2::new(0.0, 0.0),
+        };
+
+        let value = V2::new(axis.x.value(input, self.gamepad), axis.y.value(input, self.gamepad));
+        let length = (value.x * value.x + value.y * value.y).sqrt();
+        if length > 1.0 { value / length } else { value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_map() -> InputMap {
+        InputMap::new()
+            .with_action("jump", &[Binding::Key(VirtualKeyCode::Space), Binding::GamepadButton(GamepadButton::South)])
+            .with_action("fire", &[
+                Binding::Mouse(MouseButton::Left),
+                Binding::GamepadAxis { axis: GamepadAxis::RightTrigger, positive: true },
+            ])
+            .with_axis("zoom", AxisBinding::keys(VirtualKeyCode::Minus, VirtualKeyCode::Equals)
+                .with_analog(GamepadAxis::RightStickY))
+            .with_axis_2d("move", Axis2dBinding::wasd().merge(Axis2dBinding::left_stick()))
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let mut map = test_map();
+        let source = map.to_ron().unwrap();
+        assert_eq!(InputMap::from_ron(&source).unwrap(), map);
+
+        // The pad isn't saved
+        map.gamepad = Some(GamepadId(3));
+        let loaded = InputMap::from_ron(&map.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded.gamepad, None);
+        assert_eq!(loaded.actions, map.actions);
+    }
+
+    #[test]
+    fn missing_fields_default() {
+        let map = InputMap::from_ron("(actions: {\"jump\": [Key(Space)]})").unwrap();
+        assert_eq!(map.action_bindings("jump"), [Binding::Key(VirtualKeyCode::Space)]);
+        assert!(map.axes.is_empty());
+        assert!(map.axes_2d.is_empty());
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut map = test_map();
+        map.rebind_action("jump", Binding::Key(VirtualKeyCode::Space), Binding::Key(VirtualKeyCode::Up));
+        assert_eq!(map.action_bindings("jump")[0], Binding::Key(VirtualKeyCode::Up));
+
+        map.bind_action("jump", Binding::Key(VirtualKeyCode::Up));
+        assert_eq!(map.action_bindings("jump").len(), 2);
+
+        map.unbind_action("jump", Binding::GamepadButton(GamepadButton::South));
+        assert_eq!(map.action_bindings("jump"), [Binding::Key(VirtualKeyCode::Up)]);
+
+        map.bind_action("fire", Binding::Key(VirtualKeyCode::Up));
+        let mut bound: Vec<&str> = map.actions_bound_to(Binding::Key(VirtualKeyCode::Up)).collect();
+        bound.sort_unstable();
+        assert_eq!(bound, ["fire", "jump"]);
+    }
+
+    #[test]
+    fn axis_2d_is_clamped() {
+        let mut input = InputSubsystem::new();
+        input.set_input_map(test_map());
+        input.inject_key_down(VirtualKeyCode::D);
+        input.inject_key_down(VirtualKeyCode::S);
+
+        let value = input.axis_2d("move");
+        assert!((value.x * value.x + value.y * value.y - 1.0).abs() < 1e-5);
+        assert!(value.x > 0.0 && value.y > 0.0);
+        assert_eq!(input.axis_value("zoom"), 0.0);
+    }
+}
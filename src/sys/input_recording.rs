@@ -0,0 +1,175 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use winit::event::WindowEvent;
+
+use crate::sys::input::InputEvent;
+
+const RECORDING_VERSION: u32 = 2;
+
+// Input events grouped by the frame they were flushed in. Frames without events aren't stored,
+// so idle stretches cost nothing.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct InputRecording {
+    version: u32,
+    pub frame_count: u32,
+    pub frames: Vec<(u32, Vec<InputEvent>)>,
+    // How far input time advanced going into each frame, in nanoseconds. The replay advances by
+    // the same amounts so buffered presses and sequences time out the same way.
+    #[serde(default)]
+    pub frame_times: Vec<u64>,
+}
+
+impl Default for InputRecording {
+    fn default() -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            frame_count: 0,
+            frames: vec![],
+            frame_times: vec![],
+        }
+    }
+}
+
+impl InputRecording {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn frame(&self, index: u32) -> &[InputEvent] {
+        match self.frames.binary_search_by_key(&index, |(frame, _)| *frame) {
+            Ok(i) => &self.frames[i].1,
+            Err(_) => &[],
+        }
+    }
+
+    pub fn frame_time(&self, index: u32) -> Option<Duration> {
+        self.frame_times.get(index as usize).map(|nanos| Duration::from_nanos(*nanos))
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::to_string(self)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        let recording = Self::from_ron(&source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if recording.version != RECORDING_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("input recording version {} is not supported", recording.version),
+            ));
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let source = self.to_ron()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, source)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct InputRecorder {
+    recording: InputRecording,
+    current: Vec<InputEvent>,
+}
+
+impl InputRecorder {
+    pub(crate) fn new () -> Self {
+        let mut recording = InputRecording::new();
+        recording.frame_times.push(0);
+        Self {
+            recording,
+            current: vec![],
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &InputEvent) {
+        if !from_window(event) {
+            self.current.push(event.clone());
+        }
+    }
+
+    // Called on every flush with how far input time advanced into the next frame
+    pub(crate) fn next_frame(&mut self, dt: Duration) {
+        self.end_frame();
+        self.recording.frame_times.push(dt.as_nanos() as u64);
+    }
+
+    fn end_frame(&mut self) {
+        if !self.current.is_empty() {
+            let events = std::mem::take(&mut self.current);
+            self.recording.frames.push((self.recording.frame_count, events));
+        }
+        self.recording.frame_count += 1;
+    }
+
+    pub(crate) fn finish(mut self) -> InputRecording {
+        self.end_frame();
+        self.recording
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct InputReplay {
+    recording: InputRecording,
+    frame: u32,
+}
+
+impl InputReplay {
+    pub(crate) fn new (recording: InputRecording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+        }
+    }
+
+    // Window events in recordings made before they were left out are skipped
+    pub(crate) fn current_frame(&self) -> impl Iterator<Item = &InputEvent> {
+        self.recording.frame(self.frame).iter().filter(|event| !from_window(event))
+    }
+
+    // Recordings without frame times leave input time to the clock
+    pub(crate) fn next_frame_time(&self) -> Option<Duration> {
+        self.recording.frame_time(self.frame + 1)
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.frame += 1;
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frame_count
+    }
+}
+
+// The window's own state always comes from the real window, so it isn't recorded and keeps
+// passing through during a replay. Replaying a recorded resize would report a size the window
+// doesn't have.
+fn from_window(event: &InputEvent) -> bool {
+    matches!(event,
+        InputEvent::CloseRequested
+        | InputEvent::Destroyed
+        | InputEvent::Resized { .. }
+        | InputEvent::ScaleFactorChanged(_))
+}
+
+// While replaying, the OS still gets to close and resize the window. Everything else comes from
+// the recording.
+pub(crate) fn passes_during_replay(event: &WindowEvent) -> bool {
+    matches!(event,
+        WindowEvent::CloseRequested
+        | WindowEvent::Destroyed
+        | WindowEvent::Resized(_)
+        | WindowEvent::ScaleFactorChanged { .. })
+}
@@ -1,10 +1,17 @@
+use std::cell::RefCell;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use winit::dpi::PhysicalSize;
 use winit::event::{Event, MouseButton, VirtualKeyCode, WindowEvent};
 
 use crate::math::geo::V2;
-use crate::sys::input::{Input, KeyAction, mouse_button_to_int, MouseAction, TextChar};
+use crate::sys::gamepad::{DeadZones, GamepadAxis, GamepadBackend, GamepadButton, GamepadEvent, GamepadId, Gamepads};
+use crate::sys::input::{Input, InputEvent, KeyAction, mouse_button_to_int, MouseAction, TextChar};
+use crate::sys::input_history::{InputHistory, InputSequence, sequence_matches};
+use crate::sys::input_map::{AXIS_BUTTON_THRESHOLD, Binding, InputMap};
+use crate::sys::input_recording::{InputRecorder, InputRecording, InputReplay, passes_during_replay};
 
 #[derive(Clone)]
 pub struct InputSubsystem {
@@ -16,6 +23,17 @@ pub struct InputSubsystem {
     scale_factor: Option<f64>,
     destroyed: bool,
     close_requested: bool,
+    input_map: InputMap,
+    gamepads: Gamepads,
+    // The real pads while a replay drives `gamepads`, kept up to date so they can be restored
+    live_gamepads: Option<Gamepads>,
+    gamepad_backend: Option<Rc<RefCell<GamepadBackend>>>,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
+    time: Duration,
+    last_flush: Option<Instant>,
+    fixed_timestep: Option<Duration>,
+    history: InputHistory,
 }
 
 impl Default for InputSubsystem {
@@ -35,6 +53,16 @@ impl InputSubsystem {
             scale_factor: None,
             destroyed: false,
             close_requested: false,
+            input_map: InputMap::new(),
+            gamepads: Gamepads::default(),
+            live_gamepads: None,
+            gamepad_backend: None,
+            recorder: None,
+            replay: None,
+            time: Duration::ZERO,
+            last_flush: None,
+            fixed_timestep: None,
+            history: InputHistory::default(),
         }
     }
 
@@ -42,13 +70,19 @@ impl InputSubsystem {
         match &event {
             Event::NewEvents(_) => {
                 self.flush();
+                self.replay_frame();
                 false
             }
             Event::WindowEvent {event, ..} => {
-                self.process_window_event(event);
+                if self.replay.is_none() || passes_during_replay(event) {
+                    self.process_window_event(event);
+                }
                 false
             }
-            Event::MainEventsCleared => true,
+            Event::MainEventsCleared => {
+                self.poll_gamepads();
+                true
+            }
             _ => false,
         }
     }
@@ -60,6 +94,13 @@ impl InputSubsystem {
         }
     }
 
+    pub fn flush_with_input_events(&mut self, events: &[InputEvent]) {
+        self.flush();
+        for event in events {
+            self.process_input_event(event);
+        }
+    }
+
     pub fn flush(&mut self) {
         self.dropped_file = None;
         self.window_resized = None;
@@ -68,32 +109,307 @@ impl InputSubsystem {
         if let Some(current) = &mut self.current {
             current.update();
         }
+        self.gamepads.flush();
+        if let Some(live) = &mut self.live_gamepads {
+            live.flush();
+        }
+
+        let now = Instant::now();
+        let dt = match (self.replay.as_ref().and_then(|replay| replay.next_frame_time()), self.fixed_timestep) {
+            (Some(recorded), _) => recorded,
+            (None, Some(step)) => step,
+            (None, None) => self.last_flush.map_or(Duration::ZERO, |last| now - last),
+        };
+        self.last_flush = Some(now);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.next_frame(dt);
+        }
+        self.time += dt;
+        self.history.prune(self.time);
+    }
+
+    // Opens the platform gamepad backend, returns false if it isn't available
+    pub fn enable_gamepads(&mut self) -> bool {
+        if self.gamepad_backend.is_none() {
+            match GamepadBackend::new() {
+                Ok(backend) => self.gamepad_backend = Some(Rc::new(RefCell::new(backend))),
+                Err(e) => log::warn!("Could not initialize gamepads: {}", e),
+            }
+        }
+        self.gamepad_backend.is_some()
+    }
+
+    fn poll_gamepads(&mut self) {
+        let events = match &self.gamepad_backend {
+            Some(backend) => backend.borrow_mut().poll(),
+            None => return,
+        };
+        self.handle_gamepad_events(events);
+    }
+
+    // During a replay the real pads only update the state restored once it ends
+    fn handle_gamepad_events(&mut self, events: Vec<GamepadEvent>) {
+        for event in events.into_iter() {
+            match &mut self.live_gamepads {
+                Some(live) => live.handle_event(&event),
+                None => self.process_input_event(&InputEvent::Gamepad(event)),
+            }
+        }
     }
 
     fn process_window_event(&mut self, event: &WindowEvent) {
+        if let Some(event) = InputEvent::from_window_event(event) {
+            self.process_input_event(&event);
+        }
+    }
+
+    fn process_input_event(&mut self, event: &InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+
+        let axes_before = match event {
+            InputEvent::Gamepad(GamepadEvent::AxisChanged { id, .. }) => self.axis_buttons(*id),
+            _ => vec![],
+        };
+        self.record_history(event);
+
         match event {
-            WindowEvent::CloseRequested => self.close_requested = true,
-            WindowEvent::Destroyed => self.destroyed = true,
-            WindowEvent::Focused(false) => self.current = None,
-            WindowEvent::Focused(true) => {
+            InputEvent::CloseRequested => self.close_requested = true,
+            InputEvent::Destroyed => self.destroyed = true,
+            InputEvent::Focused(false) => self.current = None,
+            InputEvent::Focused(true) => {
                 if self.current.is_none() {
                     self.current = Some(Input::new());
                 }
             }
-            WindowEvent::DroppedFile(path) => self.dropped_file = Some(path.clone()),
-            WindowEvent::Resized(size) => {
-                self.window_resized = Some(*size);
-                self.window_size = Some((*size).into());
+            InputEvent::DroppedFile(path) => self.dropped_file = Some(path.clone()),
+            InputEvent::Resized { width, height } => {
+                self.window_resized = Some(PhysicalSize::new(*width, *height));
+                self.window_size = Some((*width, *height));
             }
-            WindowEvent::ScaleFactorChanged { scale_factor, ..} => {
+            InputEvent::ScaleFactorChanged(scale_factor) => {
                 self.scale_factor_changed = Some(*scale_factor);
                 self.scale_factor = Some(*scale_factor);
             }
+            InputEvent::Gamepad(event) => self.gamepads.handle_event(event),
             _ => {}
         }
 
         if let Some(current) = &mut self.current {
-            current.handle_event(event);
+            current.handle_input_event(event);
+        }
+
+        // Axes bound as buttons are pressed when they cross the threshold
+        if let InputEvent::Gamepad(GamepadEvent::AxisChanged { id, .. }) = event {
+            let axes_after = self.axis_buttons(*id);
+            for ((binding, before), (_, after)) in axes_before.into_iter().zip(axes_after.into_iter()) {
+                if before != after {
+                    self.history.record(self.time, binding, after, Some(*id));
+                }
+            }
+        }
+    }
+
+    fn record_history(&mut self, event: &InputEvent) {
+        let time = self.time;
+        match *event {
+            InputEvent::Key { key, pressed } => {
+                if !pressed || !self.key_held(key) {
+                    self.history.record(time, Binding::Key(key), pressed, None);
+                }
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                if !pressed || !self.mouse_held(button) {
+                    self.history.record(time, Binding::Mouse(button), pressed, None);
+                }
+            }
+            InputEvent::Gamepad(GamepadEvent::ButtonPressed { id, button }) => {
+                let held = self.gamepads.pads.get(&id).map_or(false, |pad| pad.button_held(button));
+                if !held {
+                    self.history.record(time, Binding::GamepadButton(button), true, Some(id));
+                }
+            }
+            InputEvent::Gamepad(GamepadEvent::ButtonReleased { id, button }) => {
+                self.history.record(time, Binding::GamepadButton(button), false, Some(id));
+            }
+            _ => {}
+        }
+    }
+
+    // Every axis of a pad as a pair of buttons, and whether each is pushed past the threshold
+    fn axis_buttons(&self, id: GamepadId) -> Vec<(Binding, bool)> {
+        let pad = match self.gamepads.pads.get(&id) {
+            Some(pad) => pad,
+            None => return vec![],
+        };
+
+        [
+            GamepadAxis::LeftStickX,
+            GamepadAxis::LeftStickY,
+            GamepadAxis::RightStickX,
+            GamepadAxis::RightStickY,
+            GamepadAxis::LeftTrigger,
+            GamepadAxis::RightTrigger,
+        ].iter()
+            .flat_map(|axis| {
+                let value = pad.axis(*axis, &self.gamepads.dead_zones);
+                [
+                    (Binding::GamepadAxis { axis: *axis, positive: true }, value >= AXIS_BUTTON_THRESHOLD),
+                    (Binding::GamepadAxis { axis: *axis, positive: false }, -value >= AXIS_BUTTON_THRESHOLD),
+                ]
+            })
+            .collect()
+    }
+
+    // Input time, advanced on every flush by the frame time or the fixed timestep. While
+    // replaying it advances by the recorded frame times instead.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    // Makes input time independent of the wall clock, use it for tests
+    pub fn set_fixed_timestep(&mut self, step: Option<Duration>) {
+        self.fixed_timestep = step;
+    }
+
+    pub fn history(&self) -> &InputHistory {
+        &self.history
+    }
+
+    // Presses older than this are forgotten, sequences and buffers can't look back further
+    pub fn set_history_length(&mut self, length: Duration) {
+        self.history.length = length;
+    }
+
+    // Buffered press, eg. a jump pressed shortly before landing still counts
+    pub fn pressed_within(&self, action: &str, window: Duration) -> bool {
+        self.history.pressed_within(&self.input_map, action, self.time, window)
+    }
+
+    // Stops the presses of an action from counting again once a buffered press was acted on
+    pub fn consume_action(&mut self, action: &str) {
+        self.history.consume(&self.input_map, action);
+    }
+
+    pub fn sequence_matched(&self, sequence: &InputSequence) -> bool {
+        sequence_matches(&self.history, &self.input_map, sequence, self.time)
+    }
+
+    // Injected events are handled exactly like events from the OS, they show up in the queries
+    // until the next flush. Lets tests drive input without a window or a `Context`, see the tests
+    // at the bottom of this file.
+    pub fn inject(&mut self, event: InputEvent) {
+        self.process_input_event(&event);
+    }
+
+    pub fn inject_key_down(&mut self, key: VirtualKeyCode) {
+        self.inject(InputEvent::Key { key, pressed: true });
+    }
+
+    pub fn inject_key_up(&mut self, key: VirtualKeyCode) {
+        self.inject(InputEvent::Key { key, pressed: false });
+    }
+
+    // Pressed and released within the same frame
+    pub fn inject_key_tap(&mut self, key: VirtualKeyCode) {
+        self.inject_key_down(key);
+        self.inject_key_up(key);
+    }
+
+    pub fn inject_mouse_down(&mut self, button: MouseButton) {
+        self.inject(InputEvent::MouseButton { button, pressed: true });
+    }
+
+    pub fn inject_mouse_up(&mut self, button: MouseButton) {
+        self.inject(InputEvent::MouseButton { button, pressed: false });
+    }
+
+    pub fn inject_mouse_position(&mut self, position: V2) {
+        self.inject(InputEvent::CursorMoved { x: position.x, y: position.y });
+    }
+
+    // In lines, positive scrolls up
+    pub fn inject_scroll(&mut self, lines: f32) {
+        self.inject(InputEvent::Scroll(lines));
+    }
+
+    pub fn inject_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.inject(InputEvent::Char(c));
+        }
+    }
+
+    pub fn inject_gamepad(&mut self, event: GamepadEvent) {
+        self.inject(InputEvent::Gamepad(event));
+    }
+
+    // Records every input event from now on. Start from `setup` so keys held before the
+    // recording started don't go missing on replay.
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(InputRecorder::new());
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recorder.take().map(|recorder| recorder.finish())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // Feeds the recording to the subsystem one frame per flush instead of the OS input. Input
+    // state is reset first so the replay starts from the same blank state as the recording.
+    pub fn start_replay(&mut self, recording: InputRecording) {
+        self.current = Some(Input::new());
+        let gamepads = std::mem::replace(&mut self.gamepads, Gamepads {
+            dead_zones: self.gamepads.dead_zones,
+            ..Gamepads::default()
+        });
+        if self.live_gamepads.is_none() {
+            self.live_gamepads = Some(gamepads);
+        }
+        self.history.clear();
+        self.replay = Some(InputReplay::new(recording));
+
+        // Events recorded before the first flush
+        self.replay_frame_events();
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+        // Pads connected before or during the replay keep working
+        if let Some(live) = self.live_gamepads.take() {
+            self.gamepads = Gamepads {
+                dead_zones: self.gamepads.dead_zones,
+                ..live
+            };
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    fn replay_frame(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            replay.advance();
+            if replay.is_finished() {
+                log::info!("Input replay finished");
+                self.stop_replay();
+                return;
+            }
+        }
+        self.replay_frame_events();
+    }
+
+    fn replay_frame_events(&mut self) {
+        let events: Vec<InputEvent> = match &self.replay {
+            Some(replay) => replay.current_frame().cloned().collect(),
+            None => return,
+        };
+        for event in events.iter() {
+            self.process_input_event(event);
         }
     }
 
@@ -177,6 +493,110 @@ impl InputSubsystem {
         }
     }
 
+    // Pads are tracked while the window is unfocused, but read as idle like the keyboard
+    pub(crate) fn gamepad_input(&self) -> Option<&Gamepads> {
+        self.current.as_ref().map(|_| &self.gamepads)
+    }
+
+    pub fn gamepads(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.gamepads.pads.keys().copied()
+    }
+
+    // Pads connected or disconnected this frame
+    pub fn gamepads_connected(&self) -> &[GamepadId] {
+        &self.gamepads.connected
+    }
+
+    pub fn gamepads_disconnected(&self) -> &[GamepadId] {
+        &self.gamepads.disconnected
+    }
+
+    pub fn gamepad_name(&self, id: GamepadId) -> Option<&str> {
+        self.gamepads.pads.get(&id).map(|pad| pad.name.as_str())
+    }
+
+    pub fn gamepad_button_pressed(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.gamepad_input()
+            .and_then(|gamepads| gamepads.pads.get(&id))
+            .map_or(false, |pad| pad.button_pressed(button))
+    }
+
+    pub fn gamepad_button_released(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.gamepad_input()
+            .and_then(|gamepads| gamepads.pads.get(&id))
+            .map_or(false, |pad| pad.button_released(button))
+    }
+
+    pub fn gamepad_button_held(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.gamepad_input()
+            .and_then(|gamepads| gamepads.pads.get(&id))
+            .map_or(false, |pad| pad.button_held(button))
+    }
+
+    // Dead zones already applied
+    pub fn gamepad_axis(&self, id: GamepadId, axis: GamepadAxis) -> f32 {
+        self.gamepad_input()
+            .and_then(|gamepads| gamepads.pads.get(&id).map(|pad| pad.axis(axis, &gamepads.dead_zones)))
+            .unwrap_or(0.0)
+    }
+
+    pub fn gamepad_left_stick(&self, id: GamepadId) -> V2 {
+        V2::new(self.gamepad_axis(id, GamepadAxis::LeftStickX), self.gamepad_axis(id, GamepadAxis::LeftStickY))
+    }
+
+    pub fn gamepad_right_stick(&self, id: GamepadId) -> V2 {
+        V2::new(self.gamepad_axis(id, GamepadAxis::RightStickX), self.gamepad_axis(id, GamepadAxis::RightStickY))
+    }
+
+    pub fn dead_zones(&self) -> DeadZones {
+        self.gamepads.dead_zones
+    }
+
+    pub fn set_dead_zones(&mut self, dead_zones: DeadZones) {
+        self.gamepads.dead_zones = dead_zones;
+    }
+
+    // Strong drives the low frequency motor and weak the high frequency one, both 0.0 to 1.0
+    pub fn rumble(&self, id: GamepadId, strong: f32, weak: f32, duration: Duration) {
+        if let Some(backend) = &self.gamepad_backend {
+            if let Err(e) = backend.borrow_mut().rumble(id, strong, weak, duration) {
+                log::warn!("Could not rumble gamepad {:?}: {}", id, e);
+            }
+        }
+    }
+
+    pub fn input_map(&self) -> &InputMap {
+        &self.input_map
+    }
+
+    pub fn input_map_mut(&mut self) -> &mut InputMap {
+        &mut self.input_map
+    }
+
+    pub fn set_input_map(&mut self, input_map: InputMap) {
+        self.input_map = input_map;
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.input_map.action_pressed(self, action)
+    }
+
+    pub fn action_held(&self, action: &str) -> bool {
+        self.input_map.action_held(self, action)
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        self.input_map.action_released(self, action)
+    }
+
+    pub fn axis_value(&self, axis: &str) -> f32 {
+        self.input_map.axis_value(self, axis)
+    }
+
+    pub fn axis_2d(&self, axis: &str) -> V2 {
+        self.input_map.axis_2d(self, axis)
+    }
+
     pub fn scroll_diff(&self) -> f32 {
         match &self.current {
             Some(current) => current.scroll_diff,
@@ -238,3 +658,285 @@ impl InputSubsystem {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::input_map::AxisBinding;
+
+    fn jump_input() -> InputSubsystem {
+        let mut input = InputSubsystem::new();
+        input.set_input_map(InputMap::new()
+            .with_action("jump", &[Binding::Key(VirtualKeyCode::Space), Binding::GamepadButton(GamepadButton::South)]));
+        input
+    }
+
+    #[test]
+    fn injected_key_across_flushes() {
+        let mut input = jump_input();
+
+        input.inject_key_down(VirtualKeyCode::Space);
+        assert!(input.key_pressed(VirtualKeyCode::Space));
+        assert!(input.key_held(VirtualKeyCode::Space));
+        assert!(input.action_pressed("jump"));
+        assert!(input.action_held("jump"));
+
+        input.flush();
+        assert!(!input.key_pressed(VirtualKeyCode::Space));
+        assert!(input.key_held(VirtualKeyCode::Space));
+        assert!(!input.action_pressed("jump"));
+        assert!(input.action_held("jump"));
+
+        input.inject_key_up(VirtualKeyCode::Space);
+        assert!(input.key_released(VirtualKeyCode::Space));
+        assert!(!input.key_held(VirtualKeyCode::Space));
+        assert!(input.action_released("jump"));
+
+        input.flush();
+        assert!(!input.key_released(VirtualKeyCode::Space));
+        assert!(!input.action_released("jump"));
+        assert!(!input.action_held("jump"));
+    }
+
+    #[test]
+    fn injected_tap() {
+        let mut input = jump_input();
+
+        input.inject_key_tap(VirtualKeyCode::Space);
+        assert!(input.key_pressed(VirtualKeyCode::Space));
+        assert!(input.key_released(VirtualKeyCode::Space));
+        assert!(!input.key_held(VirtualKeyCode::Space));
+        assert!(input.action_pressed("jump"));
+    }
+
+    #[test]
+    fn injected_gamepad_button() {
+        let mut input = jump_input();
+        let id = GamepadId(0);
+
+        input.inject_gamepad(GamepadEvent::Connected { id, name: "Test pad".to_string() });
+        input.inject_gamepad(GamepadEvent::ButtonPressed { id, button: GamepadButton::South });
+        assert!(input.gamepad_button_pressed(id, GamepadButton::South));
+        assert!(input.action_pressed("jump"));
+
+        input.flush();
+        assert!(!input.action_pressed("jump"));
+        assert!(input.action_held("jump"));
+    }
+
+    // Everything a game could read in one frame
+    #[derive(PartialEq, Debug)]
+    struct FrameQueries {
+        pressed: Vec<bool>,
+        held: Vec<bool>,
+        released: Vec<bool>,
+        jump: (bool, bool, bool),
+        jump_buffered: bool,
+        move_x: f32,
+        mouse_pos: Option<V2>,
+        mouse_left: (bool, bool),
+        scroll: f32,
+        text: String,
+        time: Duration,
+    }
+
+    fn queries(input: &InputSubsystem) -> FrameQueries {
+        let keys = [VirtualKeyCode::Space, VirtualKeyCode::A, VirtualKeyCode::D];
+        FrameQueries {
+            pressed: keys.iter().map(|key| input.key_pressed(*key)).collect(),
+            held: keys.iter().map(|key| input.key_held(*key)).collect(),
+            released: keys.iter().map(|key| input.key_released(*key)).collect(),
+            jump: (input.action_pressed("jump"), input.action_held("jump"), input.action_released("jump")),
+            jump_buffered: input.pressed_within("jump", Duration::from_millis(50)),
+            move_x: input.axis_value("move_x"),
+            mouse_pos: input.mouse_pos(),
+            mouse_left: (input.mouse_pressed(MouseButton::Left), input.mouse_held(MouseButton::Left)),
+            scroll: input.scroll_diff(),
+            text: input.text().iter()
+                .map(|c| match c { TextChar::Char(c) => *c, TextChar::Back => '\x08' })
+                .collect(),
+            time: input.time(),
+        }
+    }
+
+    fn replay_input() -> InputSubsystem {
+        let mut input = jump_input();
+        input.input_map_mut().axes.insert("move_x".to_string(), AxisBinding::keys(VirtualKeyCode::A, VirtualKeyCode::D));
+        input.set_fixed_timestep(Some(Duration::from_millis(16)));
+        input
+    }
+
+    #[test]
+    fn recording_replays_identically() {
+        let frames: Vec<Vec<InputEvent>> = vec![
+            vec![InputEvent::CursorMoved { x: 4.0, y: 8.0 }],
+            vec![InputEvent::Key { key: VirtualKeyCode::D, pressed: true }],
+            vec![],
+            vec![
+                InputEvent::Key { key: VirtualKeyCode::Space, pressed: true },
+                InputEvent::MouseButton { button: MouseButton::Left, pressed: true },
+                InputEvent::Resized { width: 640, height: 480 },
+            ],
+            vec![InputEvent::Char('a'), InputEvent::Scroll(1.5)],
+            vec![
+                InputEvent::Key { key: VirtualKeyCode::Space, pressed: false },
+                InputEvent::Key { key: VirtualKeyCode::D, pressed: false },
+                InputEvent::Key { key: VirtualKeyCode::A, pressed: true },
+            ],
+            vec![],
+            vec![InputEvent::MouseButton { button: MouseButton::Left, pressed: false }],
+        ];
+
+        let mut input = replay_input();
+        input.start_recording();
+        let mut recorded = vec![];
+        for (i, events) in frames.iter().enumerate() {
+            if i > 0 {
+                input.flush();
+            }
+            for event in events {
+                input.inject(event.clone());
+            }
+            recorded.push(queries(&input));
+        }
+        let recording = input.stop_recording().unwrap();
+        assert!(!recording.frames.iter().any(|(_, events)| events.iter().any(|event| matches!(event, InputEvent::Resized { .. }))));
+
+        let path = std::env::temp_dir().join(format!("luna_replay_test_{}.ron", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = InputRecording::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, recording);
+        assert_eq!(InputRecording::from_ron(&recording.to_ron().unwrap()).unwrap(), recording);
+
+        let mut input = replay_input();
+        input.start_replay(loaded);
+        let mut replayed = vec![queries(&input)];
+        for _ in 1..frames.len() {
+            input.update::<()>(&Event::NewEvents(winit::event::StartCause::Poll));
+            replayed.push(queries(&input));
+        }
+
+        assert_eq!(replayed, recorded);
+        assert!(input.is_replaying());
+        input.update::<()>(&Event::NewEvents(winit::event::StartCause::Poll));
+        assert!(!input.is_replaying());
+    }
+
+    #[test]
+    fn replay_takes_window_state_from_the_window() {
+        let mut recording = InputRecording::new();
+        recording.frame_count = 2;
+        recording.frames.push((0, vec![
+            InputEvent::Resized { width: 100, height: 100 },
+            InputEvent::Key { key: VirtualKeyCode::Space, pressed: true },
+        ]));
+
+        let mut input = replay_input();
+        input.start_replay(recording);
+        assert!(input.key_pressed(VirtualKeyCode::Space));
+        assert_eq!(input.window_resized(), None);
+    }
+
+    #[test]
+    fn replay_uses_recorded_frame_times() {
+        let double_tap = InputSequence::new()
+            .then(&["jump"], Duration::ZERO)
+            .then(&["jump"], Duration::from_millis(40));
+        let frame_queries = |input: &InputSubsystem| (queries(input), input.sequence_matched(&double_tap));
+
+        // Wall clock frame times, uneven on purpose
+        let frames: Vec<(u64, Vec<InputEvent>)> = vec![
+            (0, vec![InputEvent::Key { key: VirtualKeyCode::Space, pressed: true }]),
+            (20, vec![InputEvent::Key { key: VirtualKeyCode::Space, pressed: false }]),
+            (10, vec![InputEvent::Key { key: VirtualKeyCode::Space, pressed: true }]),
+            (30, vec![InputEvent::Key { key: VirtualKeyCode::Space, pressed: false }]),
+            (60, vec![InputEvent::Key { key: VirtualKeyCode::Space, pressed: true }]),
+            (5, vec![]),
+        ];
+
+        let mut input = jump_input();
+        input.start_recording();
+        let mut recorded = vec![];
+        for (i, (sleep, events)) in frames.iter().enumerate() {
+            if i > 0 {
+                std::thread::sleep(Duration::from_millis(*sleep));
+                input.flush();
+            }
+            for event in events {
+                input.inject(event.clone());
+            }
+            recorded.push(frame_queries(&input));
+        }
+        let recording = input.stop_recording().unwrap();
+        assert_eq!(recording.frame_times.len(), recording.frame_count as usize);
+        assert!(recorded.last().unwrap().0.time >= Duration::from_millis(125));
+
+        // Replayed as fast as possible, time still advances by the recorded amounts
+        let mut input = jump_input();
+        input.start_replay(recording);
+        let mut replayed = vec![frame_queries(&input)];
+        for _ in 1..frames.len() {
+            input.update::<()>(&Event::NewEvents(winit::event::StartCause::Poll));
+            replayed.push(frame_queries(&input));
+        }
+
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn replay_keeps_connected_pads() {
+        let mut input = jump_input();
+        let id = GamepadId(0);
+        input.inject_gamepad(GamepadEvent::Connected { id, name: "Test pad".to_string() });
+        input.inject_gamepad(GamepadEvent::ButtonPressed { id, button: GamepadButton::East });
+        input.flush();
+
+        let mut recording = InputRecording::new();
+        recording.frame_count = 2;
+
+        input.start_replay(recording.clone());
+        assert_eq!(input.gamepads().count(), 0);
+        // Real pad input during the replay isn't seen until it ends
+        input.handle_gamepad_events(vec![GamepadEvent::ButtonReleased { id, button: GamepadButton::East }]);
+        assert!(!input.gamepad_button_released(id, GamepadButton::East));
+        input.stop_replay();
+
+        assert_eq!(input.gamepad_name(id), Some("Test pad"));
+        assert!(!input.gamepad_button_held(id, GamepadButton::East));
+        input.inject_gamepad(GamepadEvent::ButtonPressed { id, button: GamepadButton::South });
+        assert!(input.gamepad_button_pressed(id, GamepadButton::South));
+        assert!(input.action_pressed("jump"));
+
+        // Same once a replay runs out on its own
+        input.flush();
+        input.start_replay(recording);
+        for _ in 0..2 {
+            input.update::<()>(&Event::NewEvents(winit::event::StartCause::Poll));
+        }
+        assert!(!input.is_replaying());
+        assert!(input.gamepad_button_held(id, GamepadButton::South));
+    }
+
+    #[test]
+    fn injected_mouse_and_text() {
+        let mut input = InputSubsystem::new();
+
+        input.inject_mouse_position(V2::new(10.0, 20.0));
+        input.inject_mouse_down(MouseButton::Left);
+        input.inject_scroll(2.0);
+        input.inject_text("hi");
+        assert_eq!(input.mouse_pos(), Some(V2::new(10.0, 20.0)));
+        assert!(input.mouse_pressed(MouseButton::Left));
+        assert_eq!(input.scroll_diff(), 2.0);
+        assert_eq!(input.text().len(), 2);
+
+        input.flush();
+        input.inject_mouse_position(V2::new(15.0, 20.0));
+        assert!(input.mouse_held(MouseButton::Left));
+        assert_eq!(input.mouse_diff(), V2::new(5.0, 0.0));
+        assert_eq!(input.scroll_diff(), 0.0);
+        assert!(input.text().is_empty());
+    }
+}
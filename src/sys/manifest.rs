@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::audio::audio_subsystem::SoundData;
+use crate::gfx::model::Model;
+use crate::gfx::texture::{Texture, TextureOptions};
+use crate::sys::async_loader::{LoadingHandle, LoadStatus};
+use crate::sys::resource_manager::{MusicHandle, ResourceError, ResourceManager, ShaderHandle, SoundHandle, TextureHandle};
+use crate::sys::vfs;
+
+// Describes a group of assets in RON, for example
+//
+// (
+//     assets: [
+//         (id: "tiles", path: "tilesheet.png", kind: Texture, options: (filter: Some(Nearest))),
+//         (id: "jump", path: "jump.wav", kind: Sound, options: (background: true)),
+//         (id: "theme", path: "theme.ogg", kind: Music),
+//         (id: "ship", path: "ship.glb", kind: Model),
+//     ],
+// )
+//
+// Paths are relative to the manifest.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AssetManifest {
+    pub assets: Vec<AssetEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AssetEntry {
+    pub id: String,
+    pub path: String,
+    pub kind: AssetKind,
+    #[serde(default)]
+    pub options: ImportOptions,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AssetKind {
+    Texture,
+    Sound,
+    // Streamed from disk while it plays, see `ResourceManager::load_music`
+    Music,
+    Model,
+    Shader,
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterOption {
+    Nearest,
+    Linear,
+}
+
+impl From<FilterOption> for wgpu::FilterMode {
+    fn from(filter: FilterOption) -> Self {
+        match filter {
+            FilterOption::Nearest => wgpu::FilterMode::Nearest,
+            FilterOption::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressOption {
+    ClampToEdge,
+    Repeat,
+    MirrorRepeat,
+}
+
+impl From<AddressOption> for wgpu::AddressMode {
+    fn from(address_mode: AddressOption) -> Self {
+        match address_mode {
+            AddressOption::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            AddressOption::Repeat => wgpu::AddressMode::Repeat,
+            AddressOption::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+// Options left out keep the `TextureOptions` defaults. Background assets are loaded on the loader
+// threads and become available in the bundle once `update_loading` has uploaded them. Only
+// textures and sounds can be loaded in the background, the manifest fails to load otherwise.
+#[derive(Deserialize, Debug, Copy, Clone, Default)]
+#[serde(default)]
+pub struct ImportOptions {
+    pub filter: Option<FilterOption>,
+    pub address_mode: Option<AddressOption>,
+    pub srgb: Option<bool>,
+    pub mipmaps: Option<bool>,
+    pub background: bool,
+}
+
+impl ImportOptions {
+    pub fn texture_options(&self) -> TextureOptions {
+        let mut options = TextureOptions::default();
+        if let Some(filter) = self.filter {
+            options = options.with_filter(filter.into(), filter.into());
+        }
+        if let Some(address_mode) = self.address_mode {
+            options = options.with_address_mode(address_mode.into());
+        }
+        if let Some(srgb) = self.srgb {
+            options = options.with_srgb(srgb);
+        }
+        if let Some(mipmaps) = self.mipmaps {
+            options = options.with_mipmaps(mipmaps);
+        }
+        options
+    }
+}
+
+impl AssetManifest {
+    pub fn from_ron(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+}
+
+// Handles to every asset of a manifest, looked up by the id given in the manifest. Dropping the
+// bundle releases every asset that isn't used anywhere else.
+#[derive(Default)]
+pub struct AssetBundle {
+    textures: HashMap<String, TextureHandle>,
+    sounds: HashMap<String, SoundHandle>,
+    models: HashMap<String, Model>,
+    shaders: HashMap<String, ShaderHandle>,
+    music: HashMap<String, MusicHandle>,
+
+    background_textures: HashMap<String, LoadingHandle<Texture>>,
+    background_sounds: HashMap<String, LoadingHandle<SoundData>>,
+}
+
+impl AssetBundle {
+    pub fn texture(&self, id: &str) -> Option<TextureHandle> {
+        self.textures.get(id).cloned()
+            .or_else(|| self.background_textures.get(id).and_then(|loading| loading.handle()))
+    }
+
+    pub fn sound(&self, id: &str) -> Option<SoundHandle> {
+        self.sounds.get(id).cloned()
+            .or_else(|| self.background_sounds.get(id).and_then(|loading| loading.handle()))
+    }
+
+    pub fn model(&self, id: &str) -> Option<&Model> {
+        self.models.get(id)
+    }
+
+    pub fn shader(&self, id: &str) -> Option<ShaderHandle> {
+        self.shaders.get(id).cloned()
+    }
+
+    pub fn music(&self, id: &str) -> Option<MusicHandle> {
+        self.music.get(id).cloned()
+    }
+
+    fn background_statuses(&self) -> impl Iterator<Item = LoadStatus> + '_ {
+        self.background_textures.values().map(|loading| loading.status())
+            .chain(self.background_sounds.values().map(|loading| loading.status()))
+    }
+
+    // True once every background asset has finished loading, successfully or not
+    pub fn is_loaded(&self) -> bool {
+        self.background_statuses().all(|status| status != LoadStatus::Pending)
+    }
+
+    pub fn progress(&self) -> f32 {
+        let total = self.background_textures.len() + self.background_sounds.len();
+        if total == 0 { return 1.0; }
+
+        let done = self.background_statuses().filter(|&status| status != LoadStatus::Pending).count();
+        done as f32 / total as f32
+    }
+}
+
+fn load_entry(
+    res: &mut ResourceManager,
+    bundle: &mut AssetBundle,
+    entry: &AssetEntry,
+    path: &str,
+) -> Result<(), ResourceError> {
+    let id = entry.id.clone();
+    let options = &entry.options;
+
+    match entry.kind {
+        AssetKind::Texture if options.background => {
+            let loading = res.load_texture_async(path, Some(&entry.id), Some(options.texture_options()));
+            bundle.background_textures.insert(id, loading);
+        }
+        AssetKind::Texture => {
+            let texture = res.load_texture(path, Some(&entry.id), Some(options.texture_options()))?;
+            bundle.textures.insert(id, texture);
+        }
+        AssetKind::Sound if options.background => {
+            bundle.background_sounds.insert(id, res.load_sound_async(path));
+        }
+        AssetKind::Sound => {
+            bundle.sounds.insert(id, res.load_sound(path)?);
+        }
+        AssetKind::Music | AssetKind::Model | AssetKind::Shader if options.background => {
+            return Err(ResourceError::InvalidData {
+                details: format!("asset {} is a {:?}, only textures and sounds can be loaded in the background", entry.id, entry.kind),
+            });
+        }
+        AssetKind::Music => {
+            bundle.music.insert(id, res.load_music(path)?);
+        }
+        AssetKind::Model => {
+            bundle.models.insert(id, res.load_model(path)?);
+        }
+        AssetKind::Shader => {
+            bundle.shaders.insert(id, res.load_shader(path)?);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn load_manifest(
+    res: &mut ResourceManager,
+    manifest: &AssetManifest,
+    base_dir: &str,
+) -> Result<AssetBundle, ResourceError> {
+    let mut bundle = AssetBundle::default();
+    let mut seen: HashMap<&str, &str> = HashMap::new();
+
+    for entry in manifest.assets.iter() {
+        if let Some(previous) = seen.insert(entry.id.as_str(), entry.path.as_str()) {
+            return Err(ResourceError::InvalidData {
+                details: format!("asset id {} is used for both {} and {}", entry.id, previous, entry.path),
+            });
+        }
+
+        load_entry(res, &mut bundle, entry, &vfs::join(base_dir, &entry.path))?;
+    }
+
+    Ok(bundle)
+}
@@ -1,4 +1,14 @@
 pub mod app;
 pub mod resource_manager;
 pub mod input;
-pub mod input_subsystem;
\ No newline at end of file
+pub mod input_subsystem;
+pub mod input_map;
+pub mod gamepad;
+pub mod input_recording;
+pub mod input_history;
+pub mod handle;
+pub mod hot_reload;
+pub mod async_loader;
+pub mod vfs;
+pub mod pak;
+pub mod manifest;
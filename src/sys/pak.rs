@@ -0,0 +1,307 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::sys::vfs;
+
+// Luna pak layout, all integers little endian:
+//   magic "LPAK", version: u32, entry count: u32
+//   per entry: path length: u32, utf-8 path, data offset: u64, data size: u64
+//   file data, offsets are from the start of the pak
+const PAK_MAGIC: &[u8; 4] = b"LPAK";
+const PAK_VERSION: u32 = 1;
+
+#[derive(Copy, Clone, Debug)]
+struct PakEntry {
+    offset: u64,
+    size: u64,
+}
+
+enum PakData {
+    File(PathBuf),
+    Memory(Cow<'static, [u8]>),
+}
+
+pub struct PakArchive {
+    data: PakData,
+    entries: HashMap<String, PakEntry>,
+}
+
+fn invalid_data(details: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, details.to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+// Longest entry path accepted when reading, keeps a corrupt length from allocating gigabytes
+const MAX_PATH_LEN: usize = 4096;
+
+// `data_len` is the size of the whole pak, every entry has to fit inside it
+fn read_index(reader: &mut impl Read, data_len: u64) -> io::Result<HashMap<String, PakEntry>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != PAK_MAGIC {
+        return Err(invalid_data("not a luna pak file"));
+    }
+
+    let version = read_u32(reader)?;
+    if version != PAK_VERSION {
+        return Err(invalid_data(&format!("unsupported pak version {}", version)));
+    }
+
+    // The count isn't trusted for preallocation, a truncated index fails on read instead
+    let count = read_u32(reader)?;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let len = read_u32(reader)? as usize;
+        if len > MAX_PATH_LEN {
+            return Err(invalid_data("pak entry path is too long"));
+        }
+        let mut path = vec![0; len];
+        reader.read_exact(&mut path)?;
+        let path = String::from_utf8(path).map_err(|_| invalid_data("pak entry path is not utf-8"))?;
+
+        let offset = read_u64(reader)?;
+        let size = read_u64(reader)?;
+        let end = offset.checked_add(size).ok_or_else(|| invalid_data("pak entry size overflows"))?;
+        if end > data_len {
+            return Err(invalid_data("pak entry out of bounds"));
+        }
+        entries.insert(path, PakEntry { offset, size });
+    }
+
+    Ok(entries)
+}
+
+impl PakArchive {
+    // Only the index is read up front, file data is read on demand
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let entries = read_index(&mut io::BufReader::new(file), len)?;
+
+        Ok(Self {
+            data: PakData::File(path.to_path_buf()),
+            entries,
+        })
+    }
+
+    // For paks embedded with `include_bytes!`
+    pub fn from_bytes(bytes: impl Into<Cow<'static, [u8]>>) -> io::Result<Self> {
+        let bytes = bytes.into();
+        let entries = read_index(&mut io::Cursor::new(bytes.as_ref()), bytes.len() as u64)?;
+
+        Ok(Self {
+            data: PakData::Memory(bytes),
+            entries,
+        })
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|path| path.as_str())
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let entry = *self.entries.get(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))?;
+
+        match &self.data {
+            PakData::File(pak_path) => {
+                // Each read opens its own file so the archive can be read from several threads
+                let mut file = File::open(pak_path)?;
+                // The pak may have been replaced since its index was read
+                if entry.offset + entry.size > file.metadata()?.len() {
+                    return Err(invalid_data("pak entry out of bounds"));
+                }
+                file.seek(SeekFrom::Start(entry.offset))?;
+                let mut bytes = vec![0; entry.size as usize];
+                file.read_exact(&mut bytes)?;
+                Ok(bytes)
+            }
+            PakData::Memory(data) => {
+                let start = entry.offset as usize;
+                Ok(data[start..start + entry.size as usize].to_vec())
+            }
+        }
+    }
+}
+
+// Collects files and writes them out as a pak, used by the `lunapak` tool
+#[derive(Default)]
+pub struct PakWriter {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl PakWriter {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, path: &str, bytes: Vec<u8>) {
+        self.files.insert(vfs::normalize(path), bytes);
+    }
+
+    // Adds every file below `dir`, stored relative to it under `prefix`
+    pub fn add_directory(&mut self, dir: &Path, prefix: &str) -> io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = vfs::join(prefix, &name);
+
+            if entry.file_type()?.is_dir() {
+                self.add_directory(&entry.path(), &path)?;
+            } else {
+                self.add_file(&path, std::fs::read(entry.path())?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let index_size: usize = 12 + self.files.keys().map(|path| 4 + path.len() + 16).sum::<usize>();
+
+        writer.write_all(PAK_MAGIC)?;
+        writer.write_all(&PAK_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.files.len() as u32).to_le_bytes())?;
+
+        let mut offset = index_size as u64;
+        for (path, bytes) in self.files.iter() {
+            writer.write_all(&(path.len() as u32).to_le_bytes())?;
+            writer.write_all(path.as_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            offset += bytes.len() as u64;
+        }
+
+        for bytes in self.files.values() {
+            writer.write_all(bytes)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pak() -> Vec<u8> {
+        let mut writer = PakWriter::new();
+        writer.add_file("textures/player.png", vec![1, 2, 3, 4]);
+        writer.add_file("sounds/jump.wav", vec![5; 300]);
+        writer.add_file("empty.txt", vec![]);
+
+        let mut bytes = vec![];
+        writer.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    // Offset of the first entry's offset and size in `test_pak`, entries are sorted by path
+    const FIRST_ENTRY: usize = 12 + 4 + "empty.txt".len();
+
+    fn error_kind(bytes: Vec<u8>) -> io::ErrorKind {
+        PakArchive::from_bytes(bytes).err().expect("corrupt pak was accepted").kind()
+    }
+
+    #[test]
+    fn round_trip_memory() {
+        let pak = PakArchive::from_bytes(test_pak()).unwrap();
+
+        let mut files: Vec<&str> = pak.files().collect();
+        files.sort_unstable();
+        assert_eq!(files, ["empty.txt", "sounds/jump.wav", "textures/player.png"]);
+        assert_eq!(pak.read("textures/player.png").unwrap(), [1, 2, 3, 4]);
+        assert_eq!(pak.read("sounds/jump.wav").unwrap(), vec![5; 300]);
+        assert!(pak.read("empty.txt").unwrap().is_empty());
+        assert_eq!(pak.read("missing.png").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn round_trip_file() {
+        let path = std::env::temp_dir().join(format!("luna_pak_test_{}.pak", std::process::id()));
+        std::fs::write(&path, test_pak()).unwrap();
+
+        let pak = PakArchive::open(&path);
+        let read = pak.as_ref().map(|pak| pak.read("sounds/jump.wav"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap().unwrap(), vec![5; 300]);
+    }
+
+    #[test]
+    fn bad_magic_and_version() {
+        let mut bytes = test_pak();
+        bytes[0] = b'X';
+        assert_eq!(error_kind(bytes), io::ErrorKind::InvalidData);
+
+        let mut bytes = test_pak();
+        bytes[4..8].copy_from_slice(&99u32.to_le_bytes());
+        assert_eq!(error_kind(bytes), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = test_pak();
+        assert_eq!(error_kind(bytes[..FIRST_ENTRY + 3].to_vec()), io::ErrorKind::UnexpectedEof);
+        // Index intact but the data of the last file cut off
+        assert_eq!(error_kind(bytes[..bytes.len() - 1].to_vec()), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn huge_count() {
+        let mut bytes = test_pak();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        // Fails somewhere after the real entries instead of allocating for 4 billion
+        assert!(PakArchive::from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    fn huge_path_length() {
+        let mut bytes = test_pak();
+        bytes[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(error_kind(bytes), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn entry_out_of_bounds() {
+        let mut bytes = test_pak();
+        bytes[FIRST_ENTRY + 8..FIRST_ENTRY + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(error_kind(bytes), io::ErrorKind::InvalidData);
+
+        let mut bytes = test_pak();
+        bytes[FIRST_ENTRY..FIRST_ENTRY + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        bytes[FIRST_ENTRY + 8..FIRST_ENTRY + 16].copy_from_slice(&1u64.to_le_bytes());
+        assert_eq!(error_kind(bytes), io::ErrorKind::InvalidData);
+    }
+}
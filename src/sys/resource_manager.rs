@@ -1,71 +1,892 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::audio::audio_subsystem::SoundData;
+use crate::audio::audio_subsystem::{DecodedSound, SoundData};
+use crate::audio::music::MusicData;
+use crate::audio::sfx::SfxParams;
+use crate::gfx::geometry::{GpuMesh, Mesh};
 use crate::gfx::graphics_subsystem::GraphicsSubsystem;
-use crate::gfx::texture;
-use crate::gfx::texture::Texture;
+use crate::gfx::model;
+use crate::gfx::model::Model;
+use crate::gfx::shader::Shader;
+use crate::gfx::texture::{Texture, TextureOptions};
+use crate::sys::async_loader::{AsyncLoader, DecodedAsset, JobKind, LoadingHandle};
+use crate::sys::handle::{Handle, ResourceId, ResourcePool};
+use crate::sys::hot_reload::{HotReloader, ReloadEvent};
+use crate::sys::manifest;
+use crate::sys::manifest::{AssetBundle, AssetManifest};
+use crate::sys::vfs;
+use crate::sys::vfs::Vfs;
 
-pub type ResourceID = usize;
-pub type TextureID = ResourceID;
-pub type SoundID = ResourceID;
+pub type TextureID = ResourceId<Texture>;
+pub type SoundID = ResourceId<SoundData>;
+pub type MeshID = ResourceId<GpuMesh>;
+pub type ShaderID = ResourceId<Shader>;
+pub type MusicID = ResourceId<MusicData>;
 
-pub const WHITE_TEXTURE_ID: TextureID = 0;
+pub type TextureHandle = Handle<Texture>;
+pub type SoundHandle = Handle<SoundData>;
+pub type MeshHandle = Handle<GpuMesh>;
+pub type ShaderHandle = Handle<Shader>;
+pub type MusicHandle = Handle<MusicData>;
+
+// Built in resources are inserted first and never released
+pub const WHITE_TEXTURE_ID: TextureID = ResourceId::from_raw_parts(0, 0);
+pub const MISSING_TEXTURE_ID: TextureID = ResourceId::from_raw_parts(1, 0);
+pub const SILENT_SOUND_ID: SoundID = ResourceId::from_raw_parts(0, 0);
+
+const MISSING_TEXTURE_SIZE: u32 = 16;
+const MISSING_TEXTURE_CHECKER: u32 = 4;
+
+const MAX_LOADER_THREADS: usize = 4;
+
+#[derive(Debug)]
+pub enum ResourceError {
+    NotFound { path: PathBuf },
+    Io { path: PathBuf, source: io::Error },
+    Decode { path: String, format: String, details: String },
+    Gpu { label: String, details: String },
+    InvalidData { details: String },
+}
+
+impl Display for ResourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceError::NotFound { path } =>
+                write!(f, "Resource not found: {}", path.display()),
+            ResourceError::Io { path, source } =>
+                write!(f, "Could not read {}: {}", path.display(), source),
+            ResourceError::Decode { path, format, details } =>
+                write!(f, "Could not decode {} as {}: {}", path, format, details),
+            ResourceError::Gpu { label, details } =>
+                write!(f, "Could not create GPU resource {}: {}", label, details),
+            ResourceError::InvalidData { details } =>
+                write!(f, "Invalid resource data: {}", details),
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResourceError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_resource_file(path: &Path) -> Result<Vec<u8>, ResourceError> {
+    std::fs::read(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ResourceError::NotFound { path: path.to_path_buf() },
+        _ => ResourceError::Io { path: path.to_path_buf(), source: e },
+    })
+}
+
+pub fn image_format_name(bytes: &[u8]) -> String {
+    match image::guess_format(bytes) {
+        Ok(format) => format!("{:?}", format).to_lowercase(),
+        Err(_) => "unknown image format".to_string(),
+    }
+}
+
+pub fn sound_format_name(bytes: &[u8]) -> String {
+    let name = if bytes.starts_with(b"RIFF") {
+        "wav"
+    } else if bytes.starts_with(b"OggS") {
+        "ogg"
+    } else if bytes.starts_with(b"fLaC") {
+        "flac"
+    } else if bytes.starts_with(b"ID3") || bytes.starts_with(&[0xFF, 0xFB]) {
+        "mp3"
+    } else {
+        "unknown audio format"
+    };
+    name.to_string()
+}
+
+// Runs `f` inside a wgpu error scope so validation and out of memory errors are returned instead
+// of panicking in the device's uncaptured error handler
+pub fn with_gpu_error_scope<T>(
+    device: &wgpu::Device,
+    label: &str,
+    f: impl FnOnce() -> T,
+) -> Result<T, ResourceError> {
+    device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    let result = f();
+
+    let validation = pollster::block_on(device.pop_error_scope());
+    let out_of_memory = pollster::block_on(device.pop_error_scope());
+
+    match validation.or(out_of_memory) {
+        Some(error) => Err(ResourceError::Gpu { label: label.to_string(), details: error.to_string() }),
+        None => Ok(result),
+    }
+}
+
+// Load waiting on a worker thread, finished on the main thread by `update_loading`
+enum PendingLoad {
+    Texture {
+        path: PathBuf,
+        label: Option<String>,
+        options: TextureOptions,
+        handle: LoadingHandle<Texture>,
+    },
+    Sound {
+        path: PathBuf,
+        handle: LoadingHandle<SoundData>,
+    },
+}
 
 pub struct ResourceManager {
     gfx: Rc<RefCell<GraphicsSubsystem>>,
-    textures: Vec<Texture>,
-    sounds: Vec<SoundData>,
+    vfs: Arc<Vfs>,
+    textures: ResourcePool<Texture>,
+    sounds: ResourcePool<SoundData>,
+    meshes: ResourcePool<GpuMesh>,
+    shaders: ResourcePool<Shader>,
+    music: ResourcePool<MusicData>,
+
+    hot_reloader: Option<HotReloader>,
+    reload_events: Vec<ReloadEvent>,
+
+    loader: AsyncLoader,
+    pending_loads: HashMap<u64, PendingLoad>,
+
+    fallbacks_enabled: bool,
+    missing_texture: TextureID,
+    silent_sound: SoundID,
+}
+
+fn loader_thread_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get().saturating_sub(1))
+        .unwrap_or(1)
+        .clamp(1, MAX_LOADER_THREADS)
 }
 
 impl ResourceManager {
     pub fn new (gfx: Rc<RefCell<GraphicsSubsystem>>) -> Self {
+        // The working directory is mounted first so loose files keep working, mounts added later
+        // with the same or a higher priority override it
+        let vfs = Arc::new(Vfs::new());
+        vfs.mount_directory("", ".", 0);
+
         let mut res = ResourceManager {
             gfx,
-            textures: vec![],
-            sounds: vec![],
+            vfs: vfs.clone(),
+            textures: ResourcePool::new(),
+            sounds: ResourcePool::new(),
+            meshes: ResourcePool::new(),
+            shaders: ResourcePool::new(),
+            music: ResourcePool::new(),
+            hot_reloader: None,
+            reload_events: vec![],
+            loader: AsyncLoader::new(loader_thread_count(), vfs),
+            pending_loads: HashMap::new(),
+            fallbacks_enabled: true,
+            missing_texture: MISSING_TEXTURE_ID,
+            silent_sound: SILENT_SOUND_ID,
         };
-        res.load_texture("res/white-texture.png", Some("white-texture")).unwrap();
+
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let white = res.create_texture_from_image(
+            &image::DynamicImage::ImageRgba8(white),
+            Some("white-texture"),
+            &TextureOptions::default(),
+        ).unwrap();
+        res.insert_texture(white, None, true);
+
+        // Magenta and black checkerboard, hard to miss in game
+        let missing = image::RgbaImage::from_fn(MISSING_TEXTURE_SIZE, MISSING_TEXTURE_SIZE, |x, y| {
+            if (x / MISSING_TEXTURE_CHECKER + y / MISSING_TEXTURE_CHECKER) % 2 == 0 {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+        let missing = res.create_texture_from_image(
+            &image::DynamicImage::ImageRgba8(missing),
+            Some("missing-texture"),
+            &TextureOptions::pixel_art(),
+        ).unwrap();
+        res.insert_texture(missing, None, true);
+
+        let silent = res.sounds.insert_persistent(SoundData::silent());
+        res.sounds.get_mut(silent).unwrap().id = silent;
+
         return res;
     }
 
-    pub fn load_texture (&mut self, filepath: &str, label: Option<&str>) -> Result<TextureID, io::Error> {
-        let gfx = (*self.gfx).borrow();
-        let img_bytes = std::fs::read(filepath).expect("Could not load image file!");
+    // Every loader reads through the virtual filesystem, paths are virtual paths
+    pub fn vfs(&self) -> &Arc<Vfs> { &self.vfs }
 
-        let id= self.textures.len() as TextureID;
-        let mut texture =
-            texture::Texture::from_bytes(&gfx.device, &gfx.queue, img_bytes.as_slice(), label)
-                .expect("Could not create texture!");
-        texture.id = id;
+    // When enabled, loaders log failures and hand out the missing texture / silent sound instead of
+    // returning an error
+    pub fn set_fallbacks_enabled(&mut self, enabled: bool) { self.fallbacks_enabled = enabled; }
+    pub fn fallbacks_enabled(&self) -> bool { self.fallbacks_enabled }
+
+    pub fn set_missing_texture(&mut self, id: TextureID) { self.missing_texture = id; }
+    pub fn missing_texture(&self) -> TextureID { self.missing_texture }
 
-        self.textures.push(texture);
+    pub fn set_silent_sound(&mut self, id: SoundID) { self.silent_sound = id; }
+    pub fn silent_sound(&self) -> SoundID { self.silent_sound }
 
-        Ok(id)
+    // Frees every resource whose handles have all been dropped, called once per frame by the app
+    pub fn collect_garbage(&mut self) {
+        self.textures.collect_released();
+        self.sounds.collect_released();
+        self.meshes.collect_released();
+        self.shaders.collect_released();
+        self.music.collect_released();
     }
 
-    pub fn load_sound (&mut self, filepath: &str) -> Result<SoundID, io::Error> {
-        let bytes = std::fs::read(filepath).expect("Could not load audio file!");
-        let mut sound_data = SoundData::from_bytes(bytes.as_slice())
-            .expect("Could not create sound!");
+    // Starts watching every file backed texture, sound and shader for changes. Files are polled
+    // at the given interval from `poll_hot_reload`.
+    pub fn enable_hot_reload(&mut self, poll_interval: Duration) {
+        self.hot_reloader = Some(HotReloader::new(poll_interval));
+    }
+
+    pub fn disable_hot_reload(&mut self) {
+        self.hot_reloader = None;
+    }
+
+    pub fn hot_reload_enabled(&self) -> bool {
+        self.hot_reloader.is_some()
+    }
+
+    // Events produced by the last `poll_hot_reload`
+    pub fn reload_events(&self) -> &[ReloadEvent] {
+        &self.reload_events
+    }
+
+    // Reloads changed files in place, ids and handles stay valid. Called once per frame by the app.
+    pub fn poll_hot_reload(&mut self) {
+        self.reload_events.clear();
+
+        let mut reloader = match self.hot_reloader.take() {
+            Some(reloader) => reloader,
+            None => return,
+        };
+
+        if reloader.should_poll() {
+            let textures: Vec<(PathBuf, TextureID)> = self.textures.paths()
+                .filter(|(path, _)| reloader.changed(path))
+                .map(|(path, id)| (path.to_path_buf(), id))
+                .collect();
+            for (path, id) in textures {
+                let event = match self.reload_texture(&path, id) {
+                    Ok(_) => ReloadEvent::Texture(id),
+                    Err(e) => ReloadEvent::Failed { path, error: e.to_string() },
+                };
+                self.reload_events.push(event);
+            }
+
+            let sounds: Vec<(PathBuf, SoundID)> = self.sounds.paths()
+                .filter(|(path, _)| reloader.changed(path))
+                .map(|(path, id)| (path.to_path_buf(), id))
+                .collect();
+            for (path, id) in sounds {
+                let event = match self.reload_sound(&path, id) {
+                    Ok(_) => ReloadEvent::Sound(id),
+                    Err(e) => ReloadEvent::Failed { path, error: e.to_string() },
+                };
+                self.reload_events.push(event);
+            }
+
+            let shaders: Vec<(PathBuf, ShaderID)> = self.shaders.paths()
+                .filter(|(path, _)| reloader.changed(path))
+                .map(|(path, id)| (path.to_path_buf(), id))
+                .collect();
+            for (path, id) in shaders {
+                let event = match self.reload_shader(&path, id) {
+                    Ok(_) => ReloadEvent::Shader(id),
+                    Err(e) => ReloadEvent::Failed { path, error: e.to_string() },
+                };
+                self.reload_events.push(event);
+            }
+        }
+
+        for event in self.reload_events.iter() {
+            match event {
+                ReloadEvent::Failed { path, error } => log::warn!("Could not reload {}: {}", path.display(), error),
+                event => log::info!("Reloaded {:?}", event),
+            }
+        }
+
+        self.hot_reloader = Some(reloader);
+    }
+
+    fn reload_texture(&mut self, path: &Path, id: TextureID) -> Result<(), ResourceError> {
+        let options = match self.textures.get(id) {
+            Some(texture) => texture.options,
+            None => return Ok(()),
+        };
+
+        let bytes = read_resource_file(path)?;
+        let img = image::load_from_memory(&bytes)
+            .map_err(|e| ResourceError::Decode {
+                path: path.display().to_string(),
+                format: image_format_name(&bytes),
+                details: e.to_string(),
+            })?;
+
+        let mut texture = self.create_texture_from_image(&img, path.to_str(), &options)?;
+        texture.id = id;
+        *self.textures.get_mut(id).unwrap() = texture;
+
+        Ok(())
+    }
+
+    fn reload_sound(&mut self, path: &Path, id: SoundID) -> Result<(), ResourceError> {
+        let bytes = read_resource_file(path)?;
+        let mut sound_data = SoundData::from_bytes(&bytes)
+            .map_err(|e| ResourceError::Decode {
+                path: path.display().to_string(),
+                format: sound_format_name(&bytes),
+                details: e.to_string(),
+            })?;
 
-        let id = self.sounds.len() as SoundID;
         sound_data.id = id;
+        if let Some(sound) = self.sounds.get_mut(id) {
+            // Filters stay attached across reloads
+            sound_data.filters = std::mem::take(&mut sound.filters);
+            sound_data.filters.reattach(&mut sound_data.wav);
+            *sound = sound_data;
+        }
+
+        Ok(())
+    }
+
+    fn reload_shader(&mut self, path: &Path, id: ShaderID) -> Result<(), ResourceError> {
+        let version = match self.shaders.get(id) {
+            Some(shader) => shader.version,
+            None => return Ok(()),
+        };
 
-        self.sounds.push(sound_data);
+        let mut shader = self.compile_shader(&path.display().to_string(), read_resource_file(path)?)?;
+        shader.id = id;
+        shader.version = version.wrapping_add(1);
+        *self.shaders.get_mut(id).unwrap() = shader;
 
-        Ok(id)
+        Ok(())
     }
 
-    pub fn get_sounds(&self) -> &Vec<SoundData> {&self.sounds}
-    pub fn get_sound(&self, id: SoundID) -> &SoundData {&self.sounds[id]}
+    fn compile_shader(&self, label: &str, bytes: Vec<u8>) -> Result<Shader, ResourceError> {
+        let source = String::from_utf8(bytes)
+            .map_err(|e| ResourceError::Decode {
+                path: label.to_string(),
+                format: "wgsl".to_string(),
+                details: e.to_string(),
+            })?;
 
-    pub fn get_textures(&self) -> &Vec<Texture> {
-       &self.textures
+        let gfx = (*self.gfx).borrow();
+        with_gpu_error_scope(&gfx.device, label, || {
+            Shader::from_wgsl(&gfx.device, &source, Some(label))
+        })
     }
-    pub fn get_texture(&self, id: TextureID) -> &Texture {
-        &self.textures[id]
+
+    pub fn load_shader (&mut self, filepath: &str) -> Result<ShaderHandle, ResourceError> {
+        let path = self.vfs.cache_key(filepath);
+        if let Some(id) = self.shaders.find_path(&path) {
+            return Ok(self.shaders.handle(id).unwrap());
+        }
+
+        let shader = self.compile_shader(filepath, self.vfs.read(filepath)?)?;
+        let id = self.shaders.insert(shader, Some(path));
+        self.shaders.get_mut(id).unwrap().id = id;
+
+        Ok(self.shaders.handle(id).unwrap())
+    }
+
+    fn insert_texture(&mut self, texture: Texture, path: Option<PathBuf>, persistent: bool) -> TextureID {
+        let id = if persistent {
+            self.textures.insert_persistent(texture)
+        } else {
+            self.textures.insert(texture, path)
+        };
+        self.textures.get_mut(id).unwrap().id = id;
+        id
+    }
+
+    pub fn load_texture (
+        &mut self,
+        filepath: &str,
+        label: Option<&str>,
+        options: Option<TextureOptions>,
+    ) -> Result<TextureHandle, ResourceError> {
+        match self.try_load_texture(filepath, label, options) {
+            Err(e) if self.fallbacks_enabled => {
+                log::warn!("{}, using the missing texture", e);
+                Ok(self.missing_texture_handle())
+            }
+            result => result,
+        }
+    }
+
+    fn missing_texture_handle(&self) -> TextureHandle {
+        self.textures.handle(self.missing_texture)
+            .or_else(|| self.textures.handle(MISSING_TEXTURE_ID))
+            .unwrap()
+    }
+
+    // Textures are cached by path, loading the same file twice returns a handle to the
+    // already uploaded texture, whatever options were passed the first time
+    fn try_load_texture (
+        &mut self,
+        filepath: &str,
+        label: Option<&str>,
+        options: Option<TextureOptions>,
+    ) -> Result<TextureHandle, ResourceError> {
+        let path = self.vfs.cache_key(filepath);
+        if let Some(id) = self.textures.find_path(&path) {
+            return Ok(self.textures.handle(id).unwrap());
+        }
+
+        let img_bytes = self.vfs.read(filepath)?;
+        let options = options.unwrap_or_default();
+
+        let img = image::load_from_memory(img_bytes.as_slice())
+            .map_err(|e| ResourceError::Decode {
+                path: filepath.to_string(),
+                format: image_format_name(&img_bytes),
+                details: e.to_string(),
+            })?;
+
+        let texture = self.create_texture_from_image(&img, label.or(Some(filepath)), &options)?;
+        let id = self.insert_texture(texture, Some(path), false);
+        Ok(self.textures.handle(id).unwrap())
     }
 
-}
\ No newline at end of file
+    pub fn load_sound (&mut self, filepath: &str) -> Result<SoundHandle, ResourceError> {
+        match self.try_load_sound(filepath) {
+            Err(e) if self.fallbacks_enabled => {
+                log::warn!("{}, using the silent sound", e);
+                Ok(self.silent_sound_handle())
+            }
+            result => result,
+        }
+    }
+
+    fn silent_sound_handle(&self) -> SoundHandle {
+        self.sounds.handle(self.silent_sound)
+            .or_else(|| self.sounds.handle(SILENT_SOUND_ID))
+            .unwrap()
+    }
+
+    fn try_load_sound (&mut self, filepath: &str) -> Result<SoundHandle, ResourceError> {
+        let path = self.vfs.cache_key(filepath);
+        if let Some(id) = self.sounds.find_path(&path) {
+            return Ok(self.sounds.handle(id).unwrap());
+        }
+
+        let bytes = self.vfs.read(filepath)?;
+        let sound_data = SoundData::from_bytes(bytes.as_slice())
+            .map_err(|e| ResourceError::Decode {
+                path: filepath.to_string(),
+                format: sound_format_name(&bytes),
+                details: e.to_string(),
+            })?;
+
+        let id = self.sounds.insert(sound_data, Some(path));
+        self.sounds.get_mut(id).unwrap().id = id;
+
+        Ok(self.sounds.handle(id).unwrap())
+    }
+
+    // Reads and decodes the texture on a loader thread, the upload happens in a later
+    // `update_loading`. Already loaded files are ready right away.
+    pub fn load_texture_async (
+        &mut self,
+        filepath: &str,
+        label: Option<&str>,
+        options: Option<TextureOptions>,
+    ) -> LoadingHandle<Texture> {
+        let path = self.vfs.cache_key(filepath);
+        if let Some(id) = self.textures.find_path(&path) {
+            return LoadingHandle::ready(self.textures.handle(id).unwrap());
+        }
+
+        let handle = LoadingHandle::pending();
+        let job = self.loader.submit(filepath, JobKind::Texture);
+        self.pending_loads.insert(job, PendingLoad::Texture {
+            path,
+            label: Some(label.unwrap_or(filepath).to_string()),
+            options: options.unwrap_or_default(),
+            handle: handle.clone(),
+        });
+
+        handle
+    }
+
+    pub fn load_sound_async (&mut self, filepath: &str) -> LoadingHandle<SoundData> {
+        let path = self.vfs.cache_key(filepath);
+        if let Some(id) = self.sounds.find_path(&path) {
+            return LoadingHandle::ready(self.sounds.handle(id).unwrap());
+        }
+
+        let handle = LoadingHandle::pending();
+        let job = self.loader.submit(filepath, JobKind::Sound);
+        self.pending_loads.insert(job, PendingLoad::Sound { path, handle: handle.clone() });
+
+        handle
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_loading()
+    }
+
+    // Fraction of the asynchronous loads requested since the loader was last idle that have
+    // finished, successfully or not. 1.0 when nothing is loading.
+    pub fn loading_progress(&self) -> f32 {
+        self.loader.progress()
+    }
+
+    // Uploads everything the loader threads finished decoding, called once per frame by the app
+    pub fn update_loading(&mut self) {
+        for finished in self.loader.poll() {
+            let pending = match self.pending_loads.remove(&finished.id) {
+                Some(pending) => pending,
+                None => continue,
+            };
+
+            match pending {
+                PendingLoad::Texture { path, label, options, handle } => {
+                    let result = finished.result.and_then(|asset| match asset {
+                        DecodedAsset::Image(img) => self.finish_texture_load(path, &img, label.as_deref(), &options),
+                        DecodedAsset::Sound(_) => unreachable!(),
+                    });
+                    let result = match result {
+                        Err(e) if self.fallbacks_enabled => {
+                            log::warn!("Could not load {}: {}, using the missing texture", finished.path, e);
+                            Ok(self.missing_texture_handle())
+                        }
+                        Err(e) => {
+                            log::warn!("Could not load {}: {}", finished.path, e);
+                            Err(e)
+                        }
+                        result => result,
+                    };
+                    handle.resolve(result);
+                }
+                PendingLoad::Sound { path, handle } => {
+                    let result = finished.result.and_then(|asset| match asset {
+                        DecodedAsset::Sound(decoded) => Ok(self.finish_sound_load(path, decoded)),
+                        DecodedAsset::Image(_) => unreachable!(),
+                    });
+                    let result = match result {
+                        Err(e) if self.fallbacks_enabled => {
+                            log::warn!("Could not load {}: {}, using the silent sound", finished.path, e);
+                            Ok(self.silent_sound_handle())
+                        }
+                        Err(e) => {
+                            log::warn!("Could not load {}: {}", finished.path, e);
+                            Err(e)
+                        }
+                        result => result,
+                    };
+                    handle.resolve(result);
+                }
+            }
+        }
+    }
+
+    fn finish_texture_load (
+        &mut self,
+        path: PathBuf,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<TextureHandle, ResourceError> {
+        // The same file may have been loaded while this one was in flight
+        if let Some(id) = self.textures.find_path(&path) {
+            return Ok(self.textures.handle(id).unwrap());
+        }
+
+        let texture = self.create_texture_from_image(img, label, options)?;
+        let id = self.insert_texture(texture, Some(path), false);
+        Ok(self.textures.handle(id).unwrap())
+    }
+
+    fn finish_sound_load(&mut self, path: PathBuf, decoded: DecodedSound) -> SoundHandle {
+        if let Some(id) = self.sounds.find_path(&path) {
+            return self.sounds.handle(id).unwrap();
+        }
+
+        let id = self.sounds.insert(SoundData::from_decoded(decoded), Some(path));
+        self.sounds.get_mut(id).unwrap().id = id;
+
+        self.sounds.handle(id).unwrap()
+    }
+
+    // Music is streamed while it plays, see `MusicData`
+    pub fn load_music (&mut self, filepath: &str) -> Result<MusicHandle, ResourceError> {
+        let path = self.vfs.cache_key(filepath);
+        if let Some(id) = self.music.find_path(&path) {
+            return Ok(self.music.handle(id).unwrap());
+        }
+
+        let decode_error = |format: String, e: soloud::SoloudError| ResourceError::Decode {
+            path: filepath.to_string(),
+            format,
+            details: e.to_string(),
+        };
+
+        let music_data = match self.vfs.real_path(filepath) {
+            Some(real_path) => MusicData::from_file(&real_path)
+                .map_err(|e| decode_error("music".to_string(), e))?,
+            None => {
+                let bytes = self.vfs.read(filepath)?;
+                MusicData::from_bytes(&bytes)
+                    .map_err(|e| decode_error(sound_format_name(&bytes), e))?
+            }
+        };
+
+        let id = self.music.insert(music_data, Some(path));
+        self.music.get_mut(id).unwrap().id = id;
+
+        Ok(self.music.handle(id).unwrap())
+    }
+
+    fn create_texture_from_image (
+        &self,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Texture, ResourceError> {
+        let gfx = (*self.gfx).borrow();
+
+        with_gpu_error_scope(&gfx.device, label.unwrap_or("texture"), || {
+            Texture::from_image(&gfx.device, &gfx.queue, img, label, options)
+        })?.map_err(|e| ResourceError::Gpu {
+            label: label.unwrap_or("texture").to_string(),
+            details: e.to_string(),
+        })
+    }
+
+    // Textures and sounds created from memory aren't cached or hot reloaded, and errors are always
+    // returned since there is no file to blame in the fallback warning
+    pub fn add_texture_from_image (
+        &mut self,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<TextureHandle, ResourceError> {
+        let texture = self.create_texture_from_image(img, label, options)?;
+        let id = self.insert_texture(texture, None, false);
+        Ok(self.textures.handle(id).unwrap())
+    }
+
+    // Decodes an encoded image (png, jpeg, ...) held in memory
+    pub fn add_texture_from_bytes (
+        &mut self,
+        bytes: &[u8],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<TextureHandle, ResourceError> {
+        let img = image::load_from_memory(bytes)
+            .map_err(|e| ResourceError::Decode {
+                path: label.unwrap_or("in-memory image").to_string(),
+                format: image_format_name(bytes),
+                details: e.to_string(),
+            })?;
+
+        self.add_texture_from_image(&img, label, options)
+    }
+
+    // Tightly packed RGBA8 pixels, row by row from the top left
+    pub fn add_texture_from_rgba (
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<TextureHandle, ResourceError> {
+        let expected = width as usize * height as usize * 4;
+        if width == 0 || height == 0 || pixels.len() != expected {
+            return Err(ResourceError::InvalidData {
+                details: format!("{} bytes of pixel data for a {}x{} RGBA8 texture, expected {}",
+                    pixels.len(), width, height, expected),
+            });
+        }
+
+        let img = image::RgbaImage::from_raw(width, height, pixels.to_vec()).unwrap();
+        self.add_texture_from_image(&image::DynamicImage::ImageRgba8(img), label, options)
+    }
+
+    // Decodes an encoded sound (wav, ogg, flac, mp3) held in memory
+    pub fn add_sound_from_bytes (&mut self, bytes: &[u8], label: Option<&str>) -> Result<SoundHandle, ResourceError> {
+        let sound_data = SoundData::from_bytes(bytes)
+            .map_err(|e| ResourceError::Decode {
+                path: label.unwrap_or("in-memory sound").to_string(),
+                format: sound_format_name(bytes),
+                details: e.to_string(),
+            })?;
+
+        let id = self.sounds.insert(sound_data, None);
+        self.sounds.get_mut(id).unwrap().id = id;
+
+        Ok(self.sounds.handle(id).unwrap())
+    }
+
+    // Synthesizes a retro sound effect, the same params and seed always give the same sound
+    pub fn add_generated_sound (&mut self, params: &SfxParams, seed: u64, label: Option<&str>) -> Result<SoundHandle, ResourceError> {
+        self.add_sound_from_bytes(&params.to_wav(seed), Some(label.unwrap_or("generated sound")))
+    }
+
+    // Loads every mesh, material and base color texture of an OBJ or glTF (.gltf/.glb) file
+    pub fn load_model (&mut self, filepath: &str) -> Result<Model, ResourceError> {
+        if !self.vfs.exists(filepath) {
+            return Err(ResourceError::NotFound { path: PathBuf::from(filepath) });
+        }
+
+        let extension = Path::new(filepath).extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "obj" => model::load_obj(self, filepath),
+            "gltf" | "glb" => model::load_gltf(self, filepath),
+            _ => Err(ResourceError::Decode {
+                path: filepath.to_string(),
+                format: extension,
+                details: "unsupported model format, expected obj, gltf or glb".to_string(),
+            }),
+        }
+    }
+
+    // Loads every asset listed in a RON manifest, see `manifest::AssetManifest`
+    pub fn load_manifest (&mut self, filepath: &str) -> Result<AssetBundle, ResourceError> {
+        let bytes = self.vfs.read(filepath)?;
+        let manifest = std::str::from_utf8(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|source| AssetManifest::from_ron(source).map_err(|e| e.to_string()))
+            .map_err(|details| ResourceError::Decode {
+                path: filepath.to_string(),
+                format: "ron".to_string(),
+                details,
+            })?;
+
+        manifest::load_manifest(self, &manifest, vfs::parent(filepath))
+    }
+
+    pub fn create_texture (
+        &mut self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+        options: Option<TextureOptions>,
+    ) -> Result<TextureHandle, ResourceError> {
+        if width == 0 || height == 0 {
+            return Err(ResourceError::InvalidData {
+                details: format!("Can't create a {}x{} texture", width, height),
+            });
+        }
+        // Sprites are drawn with a filtering sampler and pixels are written one by one
+        let info = format.describe();
+        if !matches!(info.sample_type, wgpu::TextureSampleType::Float { filterable: true }) || info.block_dimensions != (1, 1) {
+            return Err(ResourceError::InvalidData {
+                details: format!("{:?} can't be used for a dynamic texture, pick an uncompressed filterable color format", format),
+            });
+        }
+
+        let gfx = (*self.gfx).borrow();
+        let options = options.unwrap_or_default();
+
+        let texture = with_gpu_error_scope(&gfx.device, label.unwrap_or("dynamic texture"), || {
+            Texture::new_dynamic(&gfx.device, width, height, format, label, &options)
+        })?;
+        std::mem::drop(gfx);
+
+        let id = self.insert_texture(texture, None, false);
+        Ok(self.textures.handle(id).unwrap())
+    }
+
+    fn texture_mut(&mut self, id: TextureID) -> Result<&mut Texture, ResourceError> {
+        self.textures.get_mut(id)
+            .ok_or_else(|| ResourceError::InvalidData { details: format!("{:?} is not a loaded texture", id) })
+    }
+
+    pub fn write_texture (
+        &mut self,
+        id: TextureID,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<(), ResourceError> {
+        let gfx = self.gfx.clone();
+        let gfx = (*gfx).borrow();
+        self.texture_mut(id)?.write_region(&gfx.queue, x, y, width, height, pixels)
+            .map_err(|e| ResourceError::InvalidData { details: e.to_string() })
+    }
+
+    pub fn resize_texture (&mut self, id: TextureID, width: u32, height: u32) -> Result<(), ResourceError> {
+        let gfx = self.gfx.clone();
+        let gfx = (*gfx).borrow();
+        let texture = self.texture_mut(id)?;
+        with_gpu_error_scope(&gfx.device, "dynamic texture", || {
+            texture.resize(&gfx.device, &gfx.queue, width, height)
+        })?.map_err(|e| ResourceError::InvalidData { details: e.to_string() })
+    }
+
+    pub fn add_mesh (&mut self, mesh: &Mesh, label: Option<&str>) -> Result<MeshHandle, ResourceError> {
+        let gfx = (*self.gfx).borrow();
+
+        let gpu_mesh = with_gpu_error_scope(&gfx.device, label.unwrap_or("mesh"), || {
+            GpuMesh::from_mesh(&gfx.device, mesh, label)
+        })?;
+        std::mem::drop(gfx);
+
+        let id = self.meshes.insert(gpu_mesh, None);
+        self.meshes.get_mut(id).unwrap().id = id;
+
+        Ok(self.meshes.handle(id).unwrap())
+    }
+
+    // Unloading frees the resource right away, ids and handles still pointing at it resolve to
+    // the fallback resources from then on
+    pub fn unload_texture(&mut self, id: TextureID) -> bool { self.textures.remove(id).is_some() }
+    pub fn unload_sound(&mut self, id: SoundID) -> bool { self.sounds.remove(id).is_some() }
+    pub fn unload_mesh(&mut self, id: MeshID) -> bool { self.meshes.remove(id).is_some() }
+    pub fn unload_shader(&mut self, id: ShaderID) -> bool { self.shaders.remove(id).is_some() }
+    pub fn unload_music(&mut self, id: MusicID) -> bool { self.music.remove(id).is_some() }
+
+    pub fn try_get_shader(&self, id: ShaderID) -> Option<&Shader> { self.shaders.get(id) }
+
+    pub fn try_get_music(&self, id: MusicID) -> Option<&MusicData> { self.music.get(id) }
+
+    pub fn get_meshes(&self) -> impl Iterator<Item = &GpuMesh> { self.meshes.iter().map(|(_, m)| m) }
+    pub fn try_get_mesh(&self, id: MeshID) -> Option<&GpuMesh> { self.meshes.get(id) }
+
+    pub fn get_sounds(&self) -> impl Iterator<Item = &SoundData> { self.sounds.iter().map(|(_, s)| s) }
+    pub fn try_get_sound(&self, id: SoundID) -> Option<&SoundData> { self.sounds.get(id) }
+    pub fn try_get_sound_mut(&mut self, id: SoundID) -> Option<&mut SoundData> { self.sounds.get_mut(id) }
+    pub fn get_sound(&self, id: SoundID) -> &SoundData {
+        self.sounds.get(id)
+            .or_else(|| self.sounds.get(self.silent_sound))
+            .unwrap_or_else(|| self.sounds.get(SILENT_SOUND_ID).unwrap())
+    }
+
+    pub fn get_textures(&self) -> impl Iterator<Item = &Texture> {
+       self.textures.iter().map(|(_, t)| t)
+    }
+    pub fn try_get_texture(&self, id: TextureID) -> Option<&Texture> {
+        self.textures.get(id)
+    }
+    pub fn get_texture(&self, id: TextureID) -> &Texture {
+        self.textures.get(id)
+            .or_else(|| self.textures.get(self.missing_texture))
+            .unwrap_or_else(|| self.textures.get(MISSING_TEXTURE_ID).unwrap())
+    }
+}
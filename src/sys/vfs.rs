@@ -0,0 +1,230 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::sys::pak::PakArchive;
+use crate::sys::resource_manager::ResourceError;
+
+// Virtual paths use forward slashes and never start with one, `.` and `..` are resolved
+pub fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split(|c| c == '/' || c == '\\') {
+        match part {
+            "" | "." => {}
+            ".." => { parts.pop(); }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+// Keeps the leading slash of absolute paths, which bypass the mounts
+pub fn join(base: &str, path: &str) -> String {
+    if base.is_empty() {
+        normalize(path)
+    } else if base.starts_with('/') {
+        format!("/{}", normalize(&format!("{}/{}", base, path)))
+    } else {
+        normalize(&format!("{}/{}", base, path))
+    }
+}
+
+pub fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+pub trait MountSource: Send + Sync {
+    // Paths are relative to the mount point
+    fn contains(&self, path: &str) -> bool;
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    // Mounts backed by real files expose them so they can be watched for hot reloading
+    fn real_path(&self, _path: &str) -> Option<PathBuf> { None }
+}
+
+pub struct DirectoryMount {
+    root: PathBuf,
+}
+
+impl DirectoryMount {
+    pub fn new (root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl MountSource for DirectoryMount {
+    fn contains(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.root.join(path))
+    }
+
+    fn real_path(&self, path: &str) -> Option<PathBuf> {
+        let path = self.root.join(path);
+        std::fs::canonicalize(&path).ok().or(Some(path))
+    }
+}
+
+impl MountSource for PakArchive {
+    fn contains(&self, path: &str) -> bool {
+        PakArchive::contains(self, path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        PakArchive::read(self, path)
+    }
+}
+
+// Files compiled into the executable with `include_bytes!`, or generated at runtime
+#[derive(Default)]
+pub struct MemoryMount {
+    files: HashMap<String, Cow<'static, [u8]>>,
+}
+
+impl MemoryMount {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, path: &str, bytes: impl Into<Cow<'static, [u8]>>) -> &mut Self {
+        self.files.insert(normalize(path), bytes.into());
+        self
+    }
+}
+
+impl MountSource for MemoryMount {
+    fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files.get(path)
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MountId(u64);
+
+struct Mount {
+    id: MountId,
+    prefix: String,
+    priority: i32,
+    source: Box<dyn MountSource>,
+}
+
+impl Mount {
+    fn relative<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.prefix.is_empty() { return Some(path); }
+
+        let rest = path.strip_prefix(self.prefix.as_str())?;
+        if rest.is_empty() { return None; }
+        rest.strip_prefix('/')
+    }
+}
+
+// Maps virtual paths onto mounted directories, pak archives and in memory files. When several
+// mounts contain a file the one with the highest priority wins, ties go to the latest mount.
+// Shared with the loader threads, so mounting only needs a shared reference.
+#[derive(Default)]
+pub struct Vfs {
+    mounts: RwLock<Vec<Mount>>,
+    next_id: AtomicU64,
+}
+
+impl Vfs {
+    pub fn new () -> Self {
+        Self::default()
+    }
+
+    pub fn mount(&self, prefix: &str, priority: i32, source: impl MountSource + 'static) -> MountId {
+        let id = MountId(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let mut mounts = self.mounts.write().unwrap();
+        mounts.push(Mount {
+            id,
+            prefix: normalize(prefix),
+            priority,
+            source: Box::new(source),
+        });
+        // Highest priority first, later mounts first among equal priorities
+        mounts.sort_by_key(|mount| std::cmp::Reverse((mount.priority, mount.id.0)));
+
+        id
+    }
+
+    pub fn mount_directory(&self, prefix: &str, dir: impl Into<PathBuf>, priority: i32) -> MountId {
+        self.mount(prefix, priority, DirectoryMount::new(dir))
+    }
+
+    pub fn mount_pak(&self, prefix: &str, pak: &Path, priority: i32) -> Result<MountId, ResourceError> {
+        let archive = PakArchive::open(pak).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ResourceError::NotFound { path: pak.to_path_buf() },
+            _ => ResourceError::Io { path: pak.to_path_buf(), source: e },
+        })?;
+        Ok(self.mount(prefix, priority, archive))
+    }
+
+    pub fn mount_memory(&self, prefix: &str, files: MemoryMount, priority: i32) -> MountId {
+        self.mount(prefix, priority, files)
+    }
+
+    pub fn unmount(&self, id: MountId) -> bool {
+        let mut mounts = self.mounts.write().unwrap();
+        let count = mounts.len();
+        mounts.retain(|mount| mount.id != id);
+        mounts.len() != count
+    }
+
+    fn find<T>(&self, path: &str, f: impl FnOnce(&Mount, &str) -> T) -> Option<T> {
+        let path = normalize(path);
+        let mounts = self.mounts.read().unwrap();
+        mounts.iter()
+            .find_map(|mount| mount.relative(&path)
+                .filter(|relative| mount.source.contains(relative))
+                .map(|relative| (mount, relative)))
+            .map(|(mount, relative)| f(mount, relative))
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        if Path::new(path).is_absolute() {
+            return Path::new(path).is_file();
+        }
+        self.find(path, |_, _| ()).is_some()
+    }
+
+    // Absolute paths bypass the mounts and are read from disk directly
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, ResourceError> {
+        if Path::new(path).is_absolute() {
+            return crate::sys::resource_manager::read_resource_file(Path::new(path));
+        }
+
+        match self.find(path, |mount, relative| mount.source.read(relative)) {
+            Some(Ok(bytes)) => Ok(bytes),
+            Some(Err(e)) => Err(ResourceError::Io { path: PathBuf::from(path), source: e }),
+            None => Err(ResourceError::NotFound { path: PathBuf::from(path) }),
+        }
+    }
+
+    pub fn real_path(&self, path: &str) -> Option<PathBuf> {
+        if Path::new(path).is_absolute() {
+            return Some(PathBuf::from(path));
+        }
+        self.find(path, |mount, relative| mount.source.real_path(relative)).flatten()
+    }
+
+    // Key used to cache loaded resources. Files on disk use their canonical path so hot reloading
+    // can watch them, everything else is keyed by its virtual path.
+    pub fn cache_key(&self, path: &str) -> PathBuf {
+        match self.real_path(path) {
+            Some(real) => std::fs::canonicalize(&real).unwrap_or(real),
+            None => PathBuf::from(format!("vfs:{}", normalize(path))),
+        }
+    }
+}
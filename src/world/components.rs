@@ -1,3 +1,6 @@
+use crate::audio::audio_subsystem::Sound;
+use crate::audio::playback::{PlayParams, SoundInstance};
+use crate::audio::spatial::Emitter;
 use crate::gfx::texture::Sprite;
 use crate::math::geo::V2;
 use crate::sys::app::Context;
@@ -86,4 +89,81 @@ impl Component for SpriteComponent {
     fn shutdown(&mut self, ctx: &mut Context) {
         todo!()
     }
-}
\ No newline at end of file
+}
+
+// Positional sound attached to an entity, kept at the entity's position by `Entity::update`
+pub struct AudioEmitterComponent {
+    pub sound: Sound,
+    pub emitter: Emitter,
+    pub params: PlayParams,
+    pub play_on_start: bool,
+    pub position: V2,
+    instance: Option<SoundInstance>,
+    started: bool,
+}
+
+impl AudioEmitterComponent {
+    pub fn new (sound: Sound, emitter: Emitter, params: PlayParams) -> Self {
+        Self {
+            sound,
+            emitter,
+            params,
+            play_on_start: true,
+            position: V2::new(0.0, 0.0),
+            instance: None,
+            started: false,
+        }
+    }
+
+    pub fn play(&mut self, ctx: &mut Context) {
+        self.stop();
+        self.instance = Some(ctx.audio.play_sound_at(&self.sound, self.position, &self.emitter, &self.params));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(instance) = self.instance.take() {
+            instance.stop();
+        }
+    }
+
+    pub fn instance(&self) -> Option<&SoundInstance> {
+        self.instance.as_ref()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.instance.as_ref().map_or(false, |instance| instance.is_playing())
+    }
+}
+
+impl Component for AudioEmitterComponent {
+    fn start(&mut self, ctx: &mut Context) {
+        self.started = true;
+        if self.play_on_start {
+            self.play(ctx);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut Context) {
+        if !self.started {
+            self.start(ctx);
+        }
+
+        if let Some(instance) = &self.instance {
+            ctx.audio.set_emitter_position(instance, self.position);
+        }
+    }
+
+    fn render(&mut self, _ctx: &mut Context) {
+    }
+
+    fn shutdown(&mut self, _ctx: &mut Context) {
+        self.stop();
+    }
+}
+
+// The world drops entities without calling `shutdown`, a looping sound would keep playing
+impl Drop for AudioEmitterComponent {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
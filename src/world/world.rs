@@ -10,6 +10,7 @@ pub struct Entity {
    pub transform: TransformComponent,
    pub sprite_component: Option<SpriteComponent>,
    pub health_component: Option<HealthComponent>,
+   pub audio_emitter_component: Option<AudioEmitterComponent>,
 }
 
 impl Entity {
@@ -29,6 +30,11 @@ impl Entity {
       if let Some(sprite) = &mut self.sprite_component {
             sprite.draw_pos = self.transform.position;
       }
+
+      if let Some(emitter) = &mut self.audio_emitter_component {
+         emitter.position = self.transform.position;
+         emitter.update(ctx);
+      }
    }
 
    pub fn render(&mut self, ctx: &mut Context) {
@@ -41,6 +47,7 @@ pub struct EntityBuilder {
    transform: TransformComponent,
    sprite_component: Option<SpriteComponent>,
    health_component: Option<HealthComponent>,
+   audio_emitter_component: Option<AudioEmitterComponent>,
 }
 
 impl EntityBuilder {
@@ -54,11 +61,17 @@ impl EntityBuilder {
       self
    }
 
+   pub fn add_audio_emitter_component(mut self, emitter: AudioEmitterComponent) -> Self {
+      self.audio_emitter_component = Some(emitter);
+      self
+   }
+
    pub fn build (self) -> Entity {
       Entity {
          transform: self.transform,
          sprite_component: self.sprite_component,
          health_component: self.health_component,
+         audio_emitter_component: self.audio_emitter_component,
       }
    }
 }